@@ -0,0 +1,483 @@
+use std::fmt;
+use std::fs;
+use std::io::{Cursor, Error as IoError, ErrorKind as IoErrorKind, Read};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use byteorder::{WriteBytesExt, ReadBytesExt, BE};
+use crate::PingError;
+
+pub const DEFAULT_PORT: u16 = 25565;
+
+const SRV_SERVICE: &str = "_minecraft._tcp";
+const DNS_TYPE_SRV: u16 = 33;
+const DNS_CLASS_IN: u16 = 1;
+
+// A server address as typed by a player: `host`, `host:port`, `[v6]:port` or a bare IPv6 address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerAddress {
+    pub host: String,
+    pub port: Option<u16>
+}
+
+// Where to connect, and which hostname and port to announce in the handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedAddress {
+    pub addresses: Vec<SocketAddr>,
+    pub hostname: String,
+    pub port: u16
+}
+
+pub trait Resolver {
+    // Returns the target host and port of the preferred `_minecraft._tcp` record, if any.
+    fn lookup_srv(&self, name: &str) -> Result<Option<(String, u16)>, IoError>;
+
+    fn lookup_host(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, IoError>;
+}
+
+// Looks up SRV records with a plain UDP query to the first nameserver in /etc/resolv.conf,
+// and hosts through the operating system. Platforms without /etc/resolv.conf, such as Windows,
+// get an `Unsupported` error from `lookup_srv`, so `resolve` falls back to the default port for
+// addresses without one; implement `Resolver` to follow SRV records there.
+#[derive(Debug, Clone)]
+pub struct SystemResolver {
+    // Limit for each SRV query. Host lookups go through the operating system and its timeouts.
+    pub timeout: Duration
+}
+
+impl ServerAddress {
+    pub fn new<S: Into<String>>(host: S, port: Option<u16>) -> ServerAddress {
+        ServerAddress { host: host.into(), port }
+    }
+
+    // Follows the vanilla client: an explicit port or an IP literal skips the SRV lookup, and a
+    // failed lookup falls back to the default port. The handshake keeps the original hostname.
+    pub fn resolve<R: Resolver + ?Sized>(&self, resolver: &R) -> Result<ResolvedAddress, PingError> {
+        if let Ok(ip) = self.host.parse::<IpAddr>() {
+            let port = self.port.unwrap_or(DEFAULT_PORT);
+            return Ok(ResolvedAddress {
+                addresses: vec![SocketAddr::new(ip, port)],
+                hostname: self.host.clone(),
+                port
+            });
+        }
+
+        let (target, port) = match self.port {
+            Some(port) => (self.host.clone(), port),
+            None => resolver.lookup_srv(&format!("{}.{}", SRV_SERVICE, self.host))
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| (self.host.clone(), DEFAULT_PORT))
+        };
+
+        let addresses = resolver.lookup_host(&target, port)?;
+        if addresses.is_empty() {
+            return Err(PingError::InvalidAddress(self.to_string()));
+        }
+
+        Ok(ResolvedAddress { addresses, hostname: self.host.clone(), port })
+    }
+}
+
+impl FromStr for ServerAddress {
+    type Err = PingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PingError::InvalidAddress(s.to_owned());
+
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (host, rest) = rest.split_once(']').ok_or_else(invalid)?;
+            match rest {
+                "" => (host, None),
+                _ => (host, Some(rest.strip_prefix(':').ok_or_else(invalid)?))
+            }
+        } else if s.matches(':').count() > 1 {
+            // An unbracketed IPv6 address cannot carry a port.
+            (s, None)
+        } else {
+            match s.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (s, None)
+            }
+        };
+
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        let port = match port {
+            Some(port) => Some(port.parse::<u16>().map_err(|_| invalid())?),
+            None => None
+        };
+
+        Ok(ServerAddress::new(host, port))
+    }
+}
+
+impl fmt::Display for ServerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bracketed = self.host.contains(':');
+        match (self.port, bracketed) {
+            (Some(port), true) => write!(f, "[{}]:{}", self.host, port),
+            (Some(port), false) => write!(f, "{}:{}", self.host, port),
+            (None, _) => f.write_str(&self.host)
+        }
+    }
+}
+
+impl From<SocketAddr> for ServerAddress {
+    fn from(address: SocketAddr) -> Self {
+        ServerAddress::new(address.ip().to_string(), Some(address.port()))
+    }
+}
+
+impl Default for SystemResolver {
+    fn default() -> Self {
+        SystemResolver { timeout: Duration::from_secs(2) }
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn lookup_srv(&self, name: &str) -> Result<Option<(String, u16)>, IoError> {
+        let nameserver = system_nameserver()?;
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.subsec_nanos() as u16)
+            .unwrap_or_default();
+
+        let socket = UdpSocket::bind(match nameserver {
+            IpAddr::V4(_) => "0.0.0.0:0",
+            IpAddr::V6(_) => "[::]:0"
+        })?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        socket.connect((nameserver, 53))?;
+        socket.send(&encode_srv_query(id, name)?)?;
+
+        let mut response = [0u8; 1500];
+        let len = socket.recv(&mut response)?;
+        decode_srv_response(id, &response[..len])
+    }
+
+    fn lookup_host(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, IoError> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+fn system_nameserver() -> Result<IpAddr, IoError> {
+    let config = match fs::read_to_string("/etc/resolv.conf") {
+        Ok(config) => config,
+        Err(e) if e.kind() == IoErrorKind::NotFound => {
+            return Err(IoError::new(IoErrorKind::Unsupported, "SRV lookup needs a nameserver in /etc/resolv.conf"));
+        }
+        Err(e) => return Err(e)
+    };
+    config
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|address| address.trim().parse::<IpAddr>().ok())
+        .next()
+        .ok_or_else(|| IoError::new(IoErrorKind::NotFound, "No nameserver configured"))
+}
+
+fn encode_srv_query(id: u16, name: &str) -> Result<Vec<u8>, IoError> {
+    let mut query = Vec::new();
+    query.write_u16::<BE>(id)?;
+    query.write_u16::<BE>(0x0100)?; // standard query, recursion desired
+    query.write_u16::<BE>(1)?; // questions
+    query.write_u16::<BE>(0)?; // answers
+    query.write_u16::<BE>(0)?; // authority records
+    query.write_u16::<BE>(0)?; // additional records
+
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(IoError::new(IoErrorKind::InvalidInput, "Invalid domain name"));
+        }
+        query.write_u8(label.len() as u8)?;
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.write_u8(0)?;
+    query.write_u16::<BE>(DNS_TYPE_SRV)?;
+    query.write_u16::<BE>(DNS_CLASS_IN)?;
+    Ok(query)
+}
+
+fn decode_srv_response(id: u16, response: &[u8]) -> Result<Option<(String, u16)>, IoError> {
+    let mut cursor = Cursor::new(response);
+    if cursor.read_u16::<BE>()? != id {
+        return Err(IoError::new(IoErrorKind::InvalidData, "DNS response id mismatch"));
+    }
+    let flags = cursor.read_u16::<BE>()?;
+    if flags & 0x000F != 0 {
+        // NXDOMAIN and friends: there is simply no record to follow.
+        return Ok(None);
+    }
+    let questions = cursor.read_u16::<BE>()?;
+    let answers = cursor.read_u16::<BE>()?;
+    cursor.read_u16::<BE>()?;
+    cursor.read_u16::<BE>()?;
+
+    for _ in 0..questions {
+        read_name(&mut cursor, response)?;
+        cursor.read_u16::<BE>()?;
+        cursor.read_u16::<BE>()?;
+    }
+
+    // (priority, weight, port, target); lowest priority wins, then highest weight.
+    let mut best: Option<(u16, u16, u16, String)> = None;
+    for _ in 0..answers {
+        read_name(&mut cursor, response)?;
+        let kind = cursor.read_u16::<BE>()?;
+        let class = cursor.read_u16::<BE>()?;
+        cursor.read_u32::<BE>()?; // ttl
+        let len = cursor.read_u16::<BE>()? as u64;
+        let end = cursor.position() + len;
+
+        if kind == DNS_TYPE_SRV && class == DNS_CLASS_IN {
+            let priority = cursor.read_u16::<BE>()?;
+            let weight = cursor.read_u16::<BE>()?;
+            let port = cursor.read_u16::<BE>()?;
+            let target = read_name(&mut cursor, response)?;
+            let better = match &best {
+                Some((p, w, _, _)) => priority < *p || (priority == *p && weight > *w),
+                None => true
+            };
+            if better {
+                best = Some((priority, weight, port, target));
+            }
+        }
+        cursor.set_position(end);
+    }
+
+    // A target of "." means the service is explicitly unavailable.
+    Ok(best
+        .filter(|(_, _, _, target)| !target.is_empty())
+        .map(|(_, _, port, target)| (target, port)))
+}
+
+fn read_name(cursor: &mut Cursor<&[u8]>, message: &[u8]) -> Result<String, IoError> {
+    let mut labels = Vec::new();
+    let mut position = cursor.position() as usize;
+    let mut resume = None;
+
+    // Bound the number of compression pointers followed so a malicious loop terminates.
+    for _ in 0..128 {
+        let len = *message.get(position)
+            .ok_or_else(|| IoError::new(IoErrorKind::UnexpectedEof, "Truncated DNS name"))? as usize;
+
+        if len == 0 {
+            cursor.set_position(resume.unwrap_or(position + 1) as u64);
+            return Ok(labels.join("."));
+        } else if len & 0xC0 == 0xC0 {
+            let low = *message.get(position + 1)
+                .ok_or_else(|| IoError::new(IoErrorKind::UnexpectedEof, "Truncated DNS name"))? as usize;
+            resume.get_or_insert(position + 2);
+            position = ((len & 0x3F) << 8) | low;
+        } else {
+            let mut label = vec![0u8; len];
+            let mut reader = message.get(position + 1..)
+                .ok_or_else(|| IoError::new(IoErrorKind::UnexpectedEof, "Truncated DNS name"))?;
+            reader.read_exact(&mut label)?;
+            labels.push(String::from_utf8_lossy(&label).into_owned());
+            position += 1 + len;
+        }
+    }
+
+    Err(IoError::new(IoErrorKind::InvalidData, "DNS name compression loop"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use super::*;
+
+    fn parse(s: &str) -> Result<ServerAddress, PingError> {
+        s.parse()
+    }
+
+    #[test]
+    fn parse_host() {
+        assert_eq!(parse("mc.example.com").unwrap(), ServerAddress::new("mc.example.com", None));
+        assert_eq!(parse("mc.example.com:25566").unwrap(), ServerAddress::new("mc.example.com", Some(25566)));
+        assert_eq!(parse("127.0.0.1:1").unwrap(), ServerAddress::new("127.0.0.1", Some(1)));
+    }
+
+    #[test]
+    fn parse_ipv6() {
+        assert_eq!(parse("[::1]:25566").unwrap(), ServerAddress::new("::1", Some(25566)));
+        assert_eq!(parse("[2001:db8::1]").unwrap(), ServerAddress::new("2001:db8::1", None));
+        assert_eq!(parse("2001:db8::1").unwrap(), ServerAddress::new("2001:db8::1", None));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for invalid in ["", ":25565", "host:", "host:port", "host:65536", "host:-1", "[::1", "[::1]25565", "[]:25565"] {
+            assert!(matches!(parse(invalid), Err(PingError::InvalidAddress(raw)) if raw == invalid), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for address in ["mc.example.com", "mc.example.com:25566", "[::1]:25566", "::1"] {
+            assert_eq!(parse(address).unwrap().to_string(), address);
+        }
+    }
+
+    // Answers from fixed tables and records every lookup made.
+    struct FakeResolver {
+        srv: Result<Option<(String, u16)>, IoErrorKind>,
+        hosts: Vec<(&'static str, SocketAddr)>,
+        lookups: RefCell<Vec<String>>
+    }
+
+    impl FakeResolver {
+        fn new(srv: Result<Option<(&str, u16)>, IoErrorKind>) -> FakeResolver {
+            FakeResolver {
+                srv: srv.map(|record| record.map(|(target, port)| (String::from(target), port))),
+                hosts: vec![
+                    ("mc.example.com", "192.0.2.1:0".parse().unwrap()),
+                    ("backend.example.net", "192.0.2.2:0".parse().unwrap())
+                ],
+                lookups: RefCell::new(Vec::new())
+            }
+        }
+    }
+
+    impl Resolver for FakeResolver {
+        fn lookup_srv(&self, name: &str) -> Result<Option<(String, u16)>, IoError> {
+            self.lookups.borrow_mut().push(format!("SRV {}", name));
+            self.srv.clone().map_err(IoError::from)
+        }
+
+        fn lookup_host(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, IoError> {
+            self.lookups.borrow_mut().push(format!("A {}", host));
+            Ok(self.hosts.iter()
+                .filter(|(name, _)| *name == host)
+                .map(|(_, address)| SocketAddr::new(address.ip(), port))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn resolve_follows_srv() {
+        let resolver = FakeResolver::new(Ok(Some(("backend.example.net", 25570))));
+        let resolved = ServerAddress::new("mc.example.com", None).resolve(&resolver).unwrap();
+        assert_eq!(resolved.addresses, vec!["192.0.2.2:25570".parse().unwrap()]);
+        // The handshake names the address the player typed, with the port actually used.
+        assert_eq!(resolved.hostname, "mc.example.com");
+        assert_eq!(resolved.port, 25570);
+        assert_eq!(*resolver.lookups.borrow(), vec!["SRV _minecraft._tcp.mc.example.com", "A backend.example.net"]);
+    }
+
+    #[test]
+    fn resolve_explicit_port_skips_srv() {
+        let resolver = FakeResolver::new(Ok(Some(("backend.example.net", 25570))));
+        let resolved = ServerAddress::new("mc.example.com", Some(25566)).resolve(&resolver).unwrap();
+        assert_eq!(resolved.addresses, vec!["192.0.2.1:25566".parse().unwrap()]);
+        assert_eq!(resolved.port, 25566);
+        assert_eq!(*resolver.lookups.borrow(), vec!["A mc.example.com"]);
+    }
+
+    #[test]
+    fn resolve_ip_literal_skips_lookups() {
+        let resolver = FakeResolver::new(Ok(None));
+        let resolved = ServerAddress::new("::1", None).resolve(&resolver).unwrap();
+        assert_eq!(resolved.addresses, vec![SocketAddr::new("::1".parse().unwrap(), DEFAULT_PORT)]);
+        assert_eq!(resolved.hostname, "::1");
+        assert!(resolver.lookups.borrow().is_empty());
+    }
+
+    #[test]
+    fn resolve_falls_back_to_default_port() {
+        for srv in [Ok(None), Err(IoErrorKind::TimedOut)] {
+            let resolver = FakeResolver::new(srv);
+            let resolved = ServerAddress::new("mc.example.com", None).resolve(&resolver).unwrap();
+            assert_eq!(resolved.addresses, vec![SocketAddr::new("192.0.2.1".parse().unwrap(), DEFAULT_PORT)]);
+            assert_eq!(resolved.hostname, "mc.example.com");
+            assert_eq!(resolved.port, DEFAULT_PORT);
+        }
+    }
+
+    #[test]
+    fn resolve_unknown_host() {
+        let resolver = FakeResolver::new(Ok(None));
+        let result = ServerAddress::new("nowhere.example.org", None).resolve(&resolver);
+        assert!(matches!(result, Err(PingError::InvalidAddress(raw)) if raw == "nowhere.example.org"));
+    }
+
+    const ID: u16 = 0x1234;
+
+    // A response to the query for `_minecraft._tcp.example.com`, whose `example.com` starts at
+    // offset 28, with one SRV answer per record. Targets are given as raw wire-format names.
+    fn srv_response(flags: u16, records: &[(u16, u16, u16, &[u8])]) -> Vec<u8> {
+        let mut response = Vec::new();
+        response.write_u16::<BE>(ID).unwrap();
+        response.write_u16::<BE>(flags).unwrap();
+        response.write_u16::<BE>(1).unwrap();
+        response.write_u16::<BE>(records.len() as u16).unwrap();
+        response.write_u16::<BE>(0).unwrap();
+        response.write_u16::<BE>(0).unwrap();
+        let question = encode_srv_query(ID, "_minecraft._tcp.example.com").unwrap();
+        response.extend_from_slice(&question[12..]);
+
+        for (priority, weight, port, target) in records {
+            response.extend_from_slice(&[0xC0, 12]); // name: pointer to the question
+            response.write_u16::<BE>(DNS_TYPE_SRV).unwrap();
+            response.write_u16::<BE>(DNS_CLASS_IN).unwrap();
+            response.write_u32::<BE>(300).unwrap();
+            response.write_u16::<BE>(6 + target.len() as u16).unwrap();
+            response.write_u16::<BE>(*priority).unwrap();
+            response.write_u16::<BE>(*weight).unwrap();
+            response.write_u16::<BE>(*port).unwrap();
+            response.extend_from_slice(target);
+        }
+        response
+    }
+
+    #[test]
+    fn srv_query() {
+        let query = encode_srv_query(ID, "_minecraft._tcp.example.com.").unwrap();
+        assert_eq!(&query[..12], &[0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&query[12..], b"\x0a_minecraft\x04_tcp\x07example\x03com\x00\x00\x21\x00\x01");
+        assert!(encode_srv_query(ID, "a..b").is_err());
+    }
+
+    #[test]
+    fn srv_lowest_priority_then_highest_weight() {
+        let response = srv_response(0x8180, &[
+            (20, 100, 1, b"\x01a\xC0\x1C"),
+            (10, 5, 2, b"\x01b\xC0\x1C"),
+            (10, 50, 3, b"\x01c\xC0\x1C"),
+            (10, 50, 4, b"\x01d\xC0\x1C")
+        ]);
+        assert_eq!(decode_srv_response(ID, &response).unwrap(), Some((String::from("c.example.com"), 3)));
+    }
+
+    #[test]
+    fn srv_uncompressed_target() {
+        let response = srv_response(0x8180, &[(0, 0, 25570, b"\x02mc\x04test\x00")]);
+        assert_eq!(decode_srv_response(ID, &response).unwrap(), Some((String::from("mc.test"), 25570)));
+    }
+
+    #[test]
+    fn srv_dot_target() {
+        let response = srv_response(0x8180, &[(0, 0, 25570, b"\x00")]);
+        assert_eq!(decode_srv_response(ID, &response).unwrap(), None);
+    }
+
+    #[test]
+    fn srv_no_record() {
+        assert_eq!(decode_srv_response(ID, &srv_response(0x8180, &[])).unwrap(), None);
+        // NXDOMAIN
+        assert_eq!(decode_srv_response(ID, &srv_response(0x8183, &[])).unwrap(), None);
+    }
+
+    #[test]
+    fn srv_bad_response() {
+        let response = srv_response(0x8180, &[(0, 0, 25570, b"\x01a\xC0\x1C")]);
+        assert_eq!(decode_srv_response(ID + 1, &response).unwrap_err().kind(), IoErrorKind::InvalidData);
+        assert_eq!(decode_srv_response(ID, &response[..response.len() - 1]).unwrap_err().kind(), IoErrorKind::UnexpectedEof);
+
+        // A target pointing at itself: header, question and the answer up to its port take 63 bytes.
+        let looped = srv_response(0x8180, &[(0, 0, 25570, b"\xC0\x3F")]);
+        assert_eq!(decode_srv_response(ID, &looped).unwrap_err().kind(), IoErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::options::Timer;
+use crate::{PingError, PingOptions, PingProtocol, ServerAddress, Status, SystemResolver};
+
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    // Number of pings in flight at once.
+    pub concurrency: usize,
+    // Timeouts for each target.
+    pub ping: PingOptions,
+    // Time after which no more results are reported and no new pings are started. Pings in flight
+    // are cut short as well.
+    pub deadline: Option<Duration>,
+    // Protocol to speak, or `None` to fall back through all of them like `ping_auto`.
+    pub protocol: Option<PingProtocol>
+}
+
+// Results of `ping_many`, in the order the pings finish.
+pub struct PingMany<A> {
+    receiver: Receiver<(A, Result<Status, PingError>)>,
+    deadline: Option<Instant>
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            concurrency: 64,
+            ping: PingOptions::default(),
+            deadline: None,
+            protocol: Some(PingProtocol::Modern)
+        }
+    }
+}
+
+// Pings every address on a pool of `options.concurrency` threads, streaming results back as they
+// complete. Targets not yet started when the deadline passes are skipped.
+pub fn ping_many<I, A>(addresses: I, options: BatchOptions) -> PingMany<A>
+where
+    I: IntoIterator<Item = A>,
+    I::IntoIter: Send + 'static,
+    A: Into<ServerAddress> + Clone + Send + 'static
+{
+    let deadline = options.deadline.map(|deadline| Instant::now() + deadline);
+    let targets = Arc::new(Mutex::new(addresses.into_iter()));
+    let (sender, receiver) = mpsc::channel();
+
+    for _ in 0..options.concurrency.max(1) {
+        let targets = Arc::clone(&targets);
+        let sender = sender.clone();
+        let options = options.clone();
+        thread::spawn(move || loop {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return;
+            }
+            // A panicking iterator poisons the lock; the remaining workers just stop.
+            let target = match targets.lock().ok().and_then(|mut targets| targets.next()) {
+                Some(target) => target,
+                None => return
+            };
+            let result = ping_one(target.clone().into(), options.protocol, limit(options.ping, deadline));
+            if sender.send((target, result)).is_err() {
+                return;
+            }
+        });
+    }
+
+    PingMany { receiver, deadline }
+}
+
+// Shortens the deadline of one ping to what is left of the batch.
+fn limit(mut options: PingOptions, deadline: Option<Instant>) -> PingOptions {
+    if let Some(deadline) = deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        options.deadline = Some(options.deadline.map_or(remaining, |own| own.min(remaining)));
+    }
+    options
+}
+
+fn ping_one(address: ServerAddress, protocol: Option<PingProtocol>, options: PingOptions) -> Result<Status, PingError> {
+    let resolved = address.resolve(&SystemResolver::default())?;
+    match protocol {
+        Some(protocol) => crate::get_status_resolved(&resolved, protocol, options),
+        None => {
+            let timer = Timer::start(options);
+            crate::ping_first(&resolved.addresses, &resolved.hostname, resolved.port, &crate::FALLBACK_ORDER, false, &timer)
+                .map(|(outcome, _)| outcome.status)
+        }
+    }
+}
+
+impl<A> Iterator for PingMany<A> {
+    type Item = (A, Result<Status, PingError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.deadline {
+            Some(deadline) => match self.receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(result) => Some(result),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None
+            },
+            None => self.receiver.recv().ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::*;
+    use crate::{ChatComponent, Players};
+
+    // Counts the connections it has accepted and the most it held at once, answering each after `delay`.
+    struct SlowServer {
+        address: SocketAddr,
+        accepted: Arc<AtomicUsize>,
+        most_active: Arc<AtomicUsize>
+    }
+
+    impl SlowServer {
+        fn start(delay: Duration) -> SlowServer {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let accepted = Arc::new(AtomicUsize::new(0));
+            let most_active = Arc::new(AtomicUsize::new(0));
+            let active = Arc::new(AtomicUsize::new(0));
+            let (accepted_count, most) = (Arc::clone(&accepted), Arc::clone(&most_active));
+            thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    accepted_count.fetch_add(1, Ordering::SeqCst);
+                    let (active, most) = (Arc::clone(&active), Arc::clone(&most));
+                    thread::spawn(move || {
+                        most.fetch_max(active.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                        thread::sleep(delay);
+                        // Released before answering, so the client's next ping cannot overlap it.
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        let _ = crate::respond(&mut stream, &status(address.port()));
+                    });
+                }
+            });
+            SlowServer { address, accepted, most_active }
+        }
+    }
+
+    fn status(port: u16) -> Status {
+        Status {
+            dirty: false,
+            version: None,
+            motd: ChatComponent::from_legacy(&port.to_string()),
+            favicon: None,
+            players: Players::new(0, 1),
+            modded: None
+        }
+    }
+
+    #[test]
+    fn concurrency_cap() {
+        let server = SlowServer::start(Duration::from_millis(100));
+        let options = BatchOptions { concurrency: 2, ..BatchOptions::default() };
+
+        let results: Vec<_> = ping_many(vec![server.address; 6], options).collect();
+        assert_eq!(results.len(), 6);
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(server.accepted.load(Ordering::SeqCst), 6);
+        assert_eq!(server.most_active.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn completion_order() {
+        let slow = SlowServer::start(Duration::from_millis(500));
+        let fast = SlowServer::start(Duration::ZERO);
+        let options = BatchOptions { concurrency: 2, ..BatchOptions::default() };
+
+        let order: Vec<_> = ping_many(vec![slow.address, fast.address, fast.address], options)
+            .map(|(address, result)| {
+                assert_eq!(result.unwrap().motd.to_plain(), address.port().to_string());
+                address
+            })
+            .collect();
+        assert_eq!(order, [fast.address, fast.address, slow.address]);
+    }
+
+    #[test]
+    fn deadline() {
+        let server = SlowServer::start(Duration::from_secs(2));
+        let options = BatchOptions { concurrency: 2, deadline: Some(Duration::from_millis(300)), ..BatchOptions::default() };
+
+        let start = Instant::now();
+        let results: Vec<_> = ping_many(vec![server.address; 10], options).collect();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(results.iter().all(|(_, result)| result.is_err()));
+
+        // The pings in flight were cut short and nothing new started after them.
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(server.accepted.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,232 @@
+use std::io::{Cursor, Error as IoError, ErrorKind as IoErrorKind, Read};
+use std::net::SocketAddr;
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+use crate::options::{timed_out, Timer};
+use crate::{ChatComponent, PingError, PingOptions, Players, Version};
+
+pub const BEDROCK_DEFAULT_PORT: u16 = 19132;
+
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1C;
+// Marks RakNet offline messages, so that they cannot be mistaken for connected traffic.
+const OFFLINE_MESSAGE_MAGIC: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78
+];
+
+// What a Bedrock server (or a Geyser proxy) advertises in its Unconnected Pong, i.e.
+// `MCPE;motd;protocol;version;online;max;serverId;subMotd;gamemode;gamemodeId;portV4;portV6;`.
+// Everything after the player counts is missing from older servers.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BedrockStatus {
+    // `MCPE` for Bedrock, `MCEE` for Education Edition.
+    pub edition: String,
+    pub motd: ChatComponent,
+    pub version: Version,
+    pub players: Players,
+    // The RakNet GUID the server identifies itself with.
+    pub server_id: i64,
+    // Usually the world name.
+    pub sub_motd: Option<ChatComponent>,
+    pub game_mode: Option<String>,
+    pub port_v4: Option<u16>,
+    pub port_v6: Option<u16>
+}
+
+// Sends one Unconnected Ping and waits for the Pong. UDP gives no connection to time out, so
+// only the read timeout and the deadline of `options` apply.
+pub fn get_bedrock_status<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<BedrockStatus, PingError> {
+    let timer = Timer::start(options.into());
+    let socket = crate::udp_connect(address)?;
+
+    let time = crate::unix_millis();
+    socket.send(&encode_ping(time)?)?;
+
+    let mut buffer = [0u8; 2048];
+    loop {
+        let (timeout, phase) = timer.read()?;
+        socket.set_read_timeout(Some(timeout))?;
+        let len = socket.recv(&mut buffer).map_err(|e| timed_out(e, phase))?;
+        // Pongs to an earlier ping from the same port are stale; skip them.
+        if let Some(status) = parse_pong(&buffer[..len], time)? {
+            return Ok(status);
+        }
+    }
+}
+
+fn encode_ping(time: i64) -> Result<Vec<u8>, PingError> {
+    let mut ping = Vec::with_capacity(33);
+    ping.write_u8(UNCONNECTED_PING)?;
+    ping.write_i64::<BE>(time)?;
+    ping.extend_from_slice(&OFFLINE_MESSAGE_MAGIC);
+    ping.write_i64::<BE>(client_guid())?;
+    Ok(ping)
+}
+
+// Any value works; servers only echo it back in connected sessions.
+fn client_guid() -> i64 {
+    crate::unix_millis() ^ ((std::process::id() as i64) << 32)
+}
+
+// Returns `None` for a valid pong that answers some other ping.
+fn parse_pong(packet: &[u8], time: i64) -> Result<Option<BedrockStatus>, PingError> {
+    let mut pong = Cursor::new(packet);
+    let packet_id = pong.read_u8()?;
+    if packet_id != UNCONNECTED_PONG {
+        return Err(PingError::UnexpectedPacketId(packet_id as i32));
+    }
+    let echoed = pong.read_i64::<BE>()?;
+    let server_id = pong.read_i64::<BE>()?;
+    let mut magic = [0u8; 16];
+    pong.read_exact(&mut magic)?;
+    if magic != OFFLINE_MESSAGE_MAGIC {
+        return Err(IoError::new(IoErrorKind::InvalidData, "Missing RakNet offline message magic").into());
+    }
+    if echoed != time {
+        return Ok(None);
+    }
+
+    let len = pong.read_u16::<BE>()? as usize;
+    let mut data = vec![0u8; len];
+    pong.read_exact(&mut data)?;
+    parse_advertisement(&String::from_utf8_lossy(&data), server_id).map(Some)
+}
+
+fn parse_advertisement(advertisement: &str, server_id: i64) -> Result<BedrockStatus, PingError> {
+    let fields: Vec<&str> = advertisement.split(';').collect();
+    let field = |index: usize, name: &'static str| {
+        fields.get(index).copied().ok_or_else(|| PingError::malformed(name, advertisement))
+    };
+    let optional = |index: usize| fields.get(index).copied().filter(|value| !value.is_empty());
+
+    Ok(BedrockStatus {
+        edition: String::from(field(0, "edition")?),
+        motd: ChatComponent::from_legacy(field(1, "motd")?),
+        version: Version {
+            protocol: crate::parse_field(field(2, "protocol")?, "protocol", advertisement)?,
+            server: String::from(field(3, "version")?)
+        },
+        players: Players::new(
+            crate::parse_field(field(4, "online")?, "online", advertisement)?,
+            crate::parse_field(field(5, "max")?, "max", advertisement)?
+        ),
+        server_id,
+        sub_motd: optional(7).map(ChatComponent::from_legacy),
+        game_mode: optional(8).map(String::from),
+        port_v4: optional(10).and_then(|port| port.parse().ok()),
+        port_v6: optional(11).and_then(|port| port.parse().ok())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::Duration;
+    use super::*;
+
+    const SERVER_ID: i64 = 0x0123456789ABCDEF;
+    const ADVERTISEMENT: &str = "MCPE;\u{00a7}aBedrock test;618;1.20.40;3;10;81985529216486895;Lobby;Survival;1;19132;19133;";
+
+    fn pong(time: i64, advertisement: &str) -> Vec<u8> {
+        let mut pong = vec![UNCONNECTED_PONG];
+        pong.write_i64::<BE>(time).unwrap();
+        pong.write_i64::<BE>(SERVER_ID).unwrap();
+        pong.extend_from_slice(&OFFLINE_MESSAGE_MAGIC);
+        pong.write_u16::<BE>(advertisement.len() as u16).unwrap();
+        pong.extend_from_slice(advertisement.as_bytes());
+        pong
+    }
+
+    // Answers one ping with garbage from another socket, a stale pong and then the real one.
+    fn fake_server() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 2048];
+            let (len, client) = socket.recv_from(&mut buffer).unwrap();
+            assert_eq!(len, 33);
+            assert_eq!(buffer[0], UNCONNECTED_PING);
+            assert_eq!(&buffer[9..25], &OFFLINE_MESSAGE_MAGIC);
+            let time = i64::from_be_bytes(buffer[1..9].try_into().unwrap());
+
+            let stranger = UdpSocket::bind("127.0.0.1:0").unwrap();
+            stranger.send_to(b"not a pong", client).unwrap();
+            socket.send_to(&pong(time - 1, "MCPE;Stale;618;1.20.40;0;10;"), client).unwrap();
+            socket.send_to(&pong(time, ADVERTISEMENT), client).unwrap();
+        });
+        address
+    }
+
+    #[test]
+    fn get_status() {
+        let status = get_bedrock_status(&fake_server(), Duration::from_secs(2)).unwrap();
+        assert_eq!(status.edition, "MCPE");
+        assert_eq!(status.motd.to_plain(), "Bedrock test");
+        assert_eq!(status.version, Version { protocol: 618, server: String::from("1.20.40") });
+        assert_eq!(status.players, Players::new(3, 10));
+        assert_eq!(status.server_id, SERVER_ID);
+        assert_eq!(status.sub_motd.map(|motd| motd.to_plain()), Some(String::from("Lobby")));
+        assert_eq!(status.game_mode.as_deref(), Some("Survival"));
+        assert_eq!(status.port_v4, Some(19132));
+        assert_eq!(status.port_v6, Some(19133));
+    }
+
+    #[test]
+    fn silent_server() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let options = PingOptions::new().read_timeout(Duration::from_millis(100));
+        let result = get_bedrock_status(&socket.local_addr().unwrap(), options);
+        assert!(matches!(result, Err(PingError::Timeout(crate::TimeoutPhase::Read, _))));
+    }
+
+    #[test]
+    fn ping() {
+        let ping = encode_ping(42).unwrap();
+        assert_eq!(ping.len(), 33);
+        assert_eq!(&ping[..9], &[UNCONNECTED_PING, 0, 0, 0, 0, 0, 0, 0, 42]);
+        assert_eq!(&ping[9..25], &OFFLINE_MESSAGE_MAGIC);
+    }
+
+    #[test]
+    fn bad_pong() {
+        assert!(matches!(parse_pong(&[0x1D], 0), Err(PingError::UnexpectedPacketId(0x1D))));
+        let mut wrong_magic = pong(1, ADVERTISEMENT);
+        wrong_magic[17] = 0x01;
+        assert!(matches!(parse_pong(&wrong_magic, 1), Err(PingError::Io(e)) if e.kind() == IoErrorKind::InvalidData));
+        let truncated = pong(1, ADVERTISEMENT);
+        assert!(matches!(parse_pong(&truncated[..truncated.len() - 1], 1), Err(PingError::Io(e)) if e.kind() == IoErrorKind::UnexpectedEof));
+        assert!(parse_pong(&pong(1, ADVERTISEMENT), 2).unwrap().is_none());
+    }
+
+    #[test]
+    fn short_advertisement() {
+        // Servers before 0.15 stop after the player counts.
+        let status = parse_advertisement("MCPE;Old server;70;0.14.3;1;20", 7).unwrap();
+        assert_eq!(status.motd.to_plain(), "Old server");
+        assert_eq!(status.version, Version { protocol: 70, server: String::from("0.14.3") });
+        assert_eq!(status.players, Players::new(1, 20));
+        assert_eq!(status.server_id, 7);
+        assert_eq!(status.sub_motd, None);
+        assert_eq!(status.game_mode, None);
+        assert_eq!(status.port_v4, None);
+        assert_eq!(status.port_v6, None);
+    }
+
+    #[test]
+    fn trailing_fields() {
+        let status = parse_advertisement("MCEE;Class;618;1.20.40;0;30;1;World;Creative;1;19132;;0;extra;", 1).unwrap();
+        assert_eq!(status.edition, "MCEE");
+        assert_eq!(status.game_mode.as_deref(), Some("Creative"));
+        assert_eq!(status.port_v4, Some(19132));
+        assert_eq!(status.port_v6, None);
+    }
+
+    #[test]
+    fn malformed_advertisement() {
+        let result = parse_advertisement("MCPE;Broken;618;1.20.40;3", 1);
+        assert!(matches!(result, Err(PingError::MalformedResponse { field: "max", .. })));
+        let result = parse_advertisement("MCPE;Broken;new;1.20.40;3;10", 1);
+        assert!(matches!(result, Err(PingError::MalformedResponse { field: "protocol", .. })));
+    }
+}
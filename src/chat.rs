@@ -0,0 +1,828 @@
+use std::fmt;
+use serde_json::{Map, Value};
+
+const SECTION: char = '\u{00a7}';
+
+// A text component as found in the modern status `description`. Style fields left as `None`
+// inherit from the parent, and `extra` children follow the parent's own text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatComponent {
+    pub text: String,
+    pub color: Option<Color>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+    pub extra: Vec<ChatComponent>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    Black,
+    DarkBlue,
+    DarkGreen,
+    DarkAqua,
+    DarkRed,
+    DarkPurple,
+    Gold,
+    Gray,
+    DarkGray,
+    Blue,
+    Green,
+    Aqua,
+    Red,
+    LightPurple,
+    Yellow,
+    White,
+    // Back to the default color, `§r` in legacy text.
+    Reset,
+    // 1.16+ `#rrggbb` colors.
+    Rgb(u8, u8, u8)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorDepth {
+    // 24-bit `38;2;r;g;b` escapes.
+    TrueColor,
+    // The xterm 256-color palette, for terminals without true color support.
+    Ansi256
+}
+
+// The fully resolved style of a run of text, after inheritance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Style {
+    color: Option<Color>,
+    bold: bool,
+    italic: bool,
+    underlined: bool,
+    strikethrough: bool,
+    obfuscated: bool
+}
+
+const NAMED_COLORS: [(Color, &str, char); 16] = [
+    (Color::Black, "black", '0'),
+    (Color::DarkBlue, "dark_blue", '1'),
+    (Color::DarkGreen, "dark_green", '2'),
+    (Color::DarkAqua, "dark_aqua", '3'),
+    (Color::DarkRed, "dark_red", '4'),
+    (Color::DarkPurple, "dark_purple", '5'),
+    (Color::Gold, "gold", '6'),
+    (Color::Gray, "gray", '7'),
+    (Color::DarkGray, "dark_gray", '8'),
+    (Color::Blue, "blue", '9'),
+    (Color::Green, "green", 'a'),
+    (Color::Aqua, "aqua", 'b'),
+    (Color::Red, "red", 'c'),
+    (Color::LightPurple, "light_purple", 'd'),
+    (Color::Yellow, "yellow", 'e'),
+    (Color::White, "white", 'f')
+];
+
+impl Color {
+    pub fn from_name(name: &str) -> Option<Color> {
+        if let Some(hex) = name.strip_prefix('#') {
+            // `from_str_radix` alone would take a sign, as in `#+fffff`.
+            if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            let rgb = u32::from_str_radix(hex, 16).ok()?;
+            return Some(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+        }
+        if name == "reset" {
+            return Some(Color::Reset);
+        }
+        NAMED_COLORS.iter().find(|(_, n, _)| *n == name).map(|(color, _, _)| *color)
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Color::Reset => String::from("reset"),
+            Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            named => NAMED_COLORS.iter()
+                .find(|(color, _, _)| color == named)
+                .map(|(_, name, _)| String::from(*name))
+                .unwrap_or_default()
+        }
+    }
+
+    pub fn from_code(code: char) -> Option<Color> {
+        let code = code.to_ascii_lowercase();
+        NAMED_COLORS.iter().find(|(_, _, c)| *c == code).map(|(color, _, _)| *color)
+    }
+
+    // The color as rendered by the vanilla client, `None` for the default color.
+    pub fn rgb(&self) -> Option<(u8, u8, u8)> {
+        let rgb: u32 = match self {
+            Color::Black => 0x000000,
+            Color::DarkBlue => 0x0000AA,
+            Color::DarkGreen => 0x00AA00,
+            Color::DarkAqua => 0x00AAAA,
+            Color::DarkRed => 0xAA0000,
+            Color::DarkPurple => 0xAA00AA,
+            Color::Gold => 0xFFAA00,
+            Color::Gray => 0xAAAAAA,
+            Color::DarkGray => 0x555555,
+            Color::Blue => 0x5555FF,
+            Color::Green => 0x55FF55,
+            Color::Aqua => 0x55FFFF,
+            Color::Red => 0xFF5555,
+            Color::LightPurple => 0xFF55FF,
+            Color::Yellow => 0xFFFF55,
+            Color::White => 0xFFFFFF,
+            Color::Reset => return None,
+            Color::Rgb(r, g, b) => return Some((*r, *g, *b))
+        };
+        Some(((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+    }
+
+    // The legacy formatting code, `None` for RGB colors which have no single-character code.
+    pub fn code(&self) -> Option<char> {
+        match self {
+            Color::Reset => Some('r'),
+            Color::Rgb(..) => None,
+            named => NAMED_COLORS.iter().find(|(color, _, _)| color == named).map(|(_, _, code)| *code)
+        }
+    }
+}
+
+impl ChatComponent {
+    pub fn text<S: Into<String>>(text: S) -> ChatComponent {
+        ChatComponent { text: text.into(), ..ChatComponent::default() }
+    }
+
+    pub fn from_json_str(json: &str) -> Result<ChatComponent, serde_json::Error> {
+        Ok(ChatComponent::from_json(&serde_json::from_str(json)?))
+    }
+
+    // Accepts any of the three component shapes. Anything unrecognised becomes empty text
+    // rather than an error, since servers send all kinds of almost-components.
+    pub fn from_json(value: &Value) -> ChatComponent {
+        match value {
+            Value::String(text) => ChatComponent::text(text.as_str()),
+            Value::Number(number) => ChatComponent::text(number.to_string()),
+            Value::Bool(flag) => ChatComponent::text(flag.to_string()),
+            Value::Array(parts) => {
+                // The first element is the parent of all following ones.
+                let mut parts = parts.iter().map(ChatComponent::from_json);
+                let mut root = parts.next().unwrap_or_default();
+                root.extra.extend(parts);
+                root
+            }
+            Value::Object(object) => {
+                let flag = |key: &str| match object.get(key) {
+                    Some(Value::Bool(flag)) => Some(*flag),
+                    Some(Value::String(flag)) => flag.parse::<bool>().ok(),
+                    _ => None
+                };
+                let text = match object.get("text") {
+                    Some(Value::String(text)) => text.clone(),
+                    Some(Value::Number(number)) => number.to_string(),
+                    Some(Value::Bool(flag)) => flag.to_string(),
+                    _ => String::new()
+                };
+                let extra = match object.get("extra") {
+                    Some(Value::Array(extra)) => extra.iter().map(ChatComponent::from_json).collect(),
+                    Some(extra) => vec![ChatComponent::from_json(extra)],
+                    None => Vec::new()
+                };
+
+                ChatComponent {
+                    text,
+                    color: object.get("color").and_then(Value::as_str).and_then(Color::from_name),
+                    bold: flag("bold"),
+                    italic: flag("italic"),
+                    underlined: flag("underlined"),
+                    strikethrough: flag("strikethrough"),
+                    obfuscated: flag("obfuscated"),
+                    extra
+                }
+            }
+            Value::Null => ChatComponent::default()
+        }
+    }
+
+    // Plain components serialize back to a bare string, everything else to an object.
+    pub fn to_json(&self) -> Value {
+        let styled = self.color.is_some()
+            || self.bold.is_some()
+            || self.italic.is_some()
+            || self.underlined.is_some()
+            || self.strikethrough.is_some()
+            || self.obfuscated.is_some();
+        if !styled && self.extra.is_empty() {
+            return Value::String(self.text.clone());
+        }
+
+        let mut object = Map::new();
+        object.insert(String::from("text"), Value::String(self.text.clone()));
+        if let Some(color) = self.color {
+            object.insert(String::from("color"), Value::String(color.name()));
+        }
+        for (key, flag) in [
+            ("bold", self.bold),
+            ("italic", self.italic),
+            ("underlined", self.underlined),
+            ("strikethrough", self.strikethrough),
+            ("obfuscated", self.obfuscated)
+        ] {
+            if let Some(flag) = flag {
+                object.insert(String::from(key), Value::Bool(flag));
+            }
+        }
+        if !self.extra.is_empty() {
+            object.insert(String::from("extra"), Value::Array(self.extra.iter().map(ChatComponent::to_json).collect()));
+        }
+        Value::Object(object)
+    }
+
+    // Parses `§`-coded text into a root with one child per styled run. A color code resets the
+    // formatting codes before it, `§r` resets everything and `§x§r§r§g§g§b§b` is an RGB color.
+    pub fn from_legacy(text: &str) -> ChatComponent {
+        let mut root = ChatComponent::default();
+        let mut style = Style::default();
+        let mut run = String::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c != SECTION {
+                run.push(c);
+                continue;
+            }
+            let code = match chars.peek() {
+                Some(code) => code.to_ascii_lowercase(),
+                None => {
+                    run.push(c);
+                    break;
+                }
+            };
+
+            let mut next = style;
+            match code {
+                'k' => next.obfuscated = true,
+                'l' => next.bold = true,
+                'm' => next.strikethrough = true,
+                'n' => next.underlined = true,
+                'o' => next.italic = true,
+                'r' => next = Style::default(),
+                'x' => match parse_legacy_rgb(chars.clone().skip(1)) {
+                    Some(color) => {
+                        next = Style { color: Some(color), ..Style::default() };
+                        // Skip the `x` and the six `§<digit>` pairs but one character, which is
+                        // consumed below like any other code.
+                        for _ in 0..12 {
+                            chars.next();
+                        }
+                    }
+                    None => {
+                        run.push(c);
+                        continue;
+                    }
+                },
+                code => match Color::from_code(code) {
+                    Some(color) => next = Style { color: Some(color), ..Style::default() },
+                    None => {
+                        // Not a formatting code; keep the text as it was.
+                        run.push(c);
+                        continue;
+                    }
+                }
+            }
+            chars.next();
+
+            if next != style {
+                if !run.is_empty() {
+                    root.extra.push(style.component(std::mem::take(&mut run)));
+                }
+                style = next;
+            }
+        }
+        if !run.is_empty() {
+            root.extra.push(style.component(run));
+        }
+
+        // Unstyled text does not need the wrapper.
+        match root.extra.as_slice() {
+            [] => ChatComponent::default(),
+            [only] if only.is_unstyled() => only.clone(),
+            _ => root
+        }
+    }
+
+    // Emits the fewest codes needed to reproduce each run's style, so text produced by
+    // `from_legacy` converts back to the same string.
+    pub fn to_legacy(&self) -> String {
+        let mut out = String::new();
+        let mut current = Style::default();
+        self.walk(Style::default(), &mut |text, style| {
+            if style != current {
+                let keeps_formatting = style.color == current.color
+                    && (style.bold || !current.bold)
+                    && (style.italic || !current.italic)
+                    && (style.underlined || !current.underlined)
+                    && (style.strikethrough || !current.strikethrough)
+                    && (style.obfuscated || !current.obfuscated);
+                let base = if keeps_formatting {
+                    current
+                } else {
+                    match style.color {
+                        Some(Color::Rgb(r, g, b)) => {
+                            out.push(SECTION);
+                            out.push('x');
+                            for digit in format!("{:02x}{:02x}{:02x}", r, g, b).chars() {
+                                out.push(SECTION);
+                                out.push(digit);
+                            }
+                        }
+                        Some(color) => {
+                            out.push(SECTION);
+                            out.push(color.code().unwrap_or('r'));
+                        }
+                        None => {
+                            out.push(SECTION);
+                            out.push('r');
+                        }
+                    }
+                    Style { color: style.color, ..Style::default() }
+                };
+                for (code, on, was) in [
+                    ('k', style.obfuscated, base.obfuscated),
+                    ('l', style.bold, base.bold),
+                    ('m', style.strikethrough, base.strikethrough),
+                    ('n', style.underlined, base.underlined),
+                    ('o', style.italic, base.italic)
+                ] {
+                    if on && !was {
+                        out.push(SECTION);
+                        out.push(code);
+                    }
+                }
+                current = style;
+            }
+            out.push_str(text);
+        });
+        out
+    }
+
+    // All text with formatting removed, including `§` codes embedded in JSON text.
+    pub fn to_plain(&self) -> String {
+        let mut out = String::new();
+        self.walk(Style::default(), &mut |text, _| out.push_str(&strip_codes(text)));
+        out
+    }
+
+    // Text with SGR escape sequences, reset at the end. Obfuscated text blinks, the closest
+    // a terminal gets to the client's scrambling.
+    pub fn to_ansi(&self, depth: ColorDepth) -> String {
+        let mut out = String::new();
+        self.walk_formatted(Style::default(), &mut |text, style| {
+            let mut codes = vec![String::from("0")];
+            for (code, on) in [
+                ("1", style.bold),
+                ("3", style.italic),
+                ("4", style.underlined),
+                ("5", style.obfuscated),
+                ("9", style.strikethrough)
+            ] {
+                if on {
+                    codes.push(String::from(code));
+                }
+            }
+            if let Some((r, g, b)) = style.color.and_then(|color| color.rgb()) {
+                codes.push(match depth {
+                    ColorDepth::TrueColor => format!("38;2;{};{};{}", r, g, b),
+                    ColorDepth::Ansi256 => format!("38;5;{}", ansi256(r, g, b))
+                });
+            }
+            out.push_str(&format!("\x1b[{}m", codes.join(";")));
+            out.push_str(text);
+        });
+        if !out.is_empty() {
+            out.push_str("\x1b[0m");
+        }
+        out
+    }
+
+    // HTML-escaped text in `<span>`s with inline styles. Obfuscated runs get an `obfuscated`
+    // class for the page to animate. Line breaks become `<br>`.
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        self.walk_formatted(Style::default(), &mut |text, style| {
+            let mut css = Vec::new();
+            if let Some((r, g, b)) = style.color.and_then(|color| color.rgb()) {
+                css.push(format!("color:#{:02x}{:02x}{:02x}", r, g, b));
+            }
+            if style.bold {
+                css.push(String::from("font-weight:bold"));
+            }
+            if style.italic {
+                css.push(String::from("font-style:italic"));
+            }
+            let decorations: Vec<&str> = [("underline", style.underlined), ("line-through", style.strikethrough)]
+                .iter()
+                .filter(|(_, on)| *on)
+                .map(|(decoration, _)| *decoration)
+                .collect();
+            if !decorations.is_empty() {
+                css.push(format!("text-decoration:{}", decorations.join(" ")));
+            }
+
+            let escaped = escape_html(text);
+            if css.is_empty() && !style.obfuscated {
+                out.push_str(&escaped);
+                return;
+            }
+            out.push_str("<span");
+            if style.obfuscated {
+                out.push_str(" class=\"obfuscated\"");
+            }
+            if !css.is_empty() {
+                out.push_str(&format!(" style=\"{}\"", css.join(";")));
+            }
+            out.push('>');
+            out.push_str(&escaped);
+            out.push_str("</span>");
+        });
+        out
+    }
+
+    fn is_unstyled(&self) -> bool {
+        self.extra.is_empty() && *self == ChatComponent::text(self.text.as_str())
+    }
+
+    fn walk<F: FnMut(&str, Style)>(&self, parent: Style, f: &mut F) {
+        let mut style = Style {
+            color: self.color.or(parent.color),
+            bold: self.bold.unwrap_or(parent.bold),
+            italic: self.italic.unwrap_or(parent.italic),
+            underlined: self.underlined.unwrap_or(parent.underlined),
+            strikethrough: self.strikethrough.unwrap_or(parent.strikethrough),
+            obfuscated: self.obfuscated.unwrap_or(parent.obfuscated)
+        };
+        if style.color == Some(Color::Reset) {
+            style.color = None;
+        }
+        if !self.text.is_empty() {
+            f(&self.text, style);
+        }
+        for child in &self.extra {
+            child.walk(style, f);
+        }
+    }
+}
+
+impl ChatComponent {
+    // Like `walk`, but also honours `§` codes that servers embed in JSON text. As in vanilla, a
+    // color code drops the formatting inherited from the component, and `§r` restores it.
+    fn walk_formatted<F: FnMut(&str, Style)>(&self, parent: Style, f: &mut F) {
+        self.walk(parent, &mut |text, style| {
+            if !text.contains(SECTION) {
+                f(text, style);
+                return;
+            }
+            let legacy = ChatComponent::from_legacy(text);
+            let runs = if legacy.extra.is_empty() { std::slice::from_ref(&legacy) } else { &legacy.extra[..] };
+            for run in runs {
+                let base = if run.color.is_some() { Style { color: style.color, ..Style::default() } } else { style };
+                run.walk(base, &mut |text, style| f(&strip_codes(text), style));
+            }
+        });
+    }
+}
+
+impl Style {
+    fn component(&self, text: String) -> ChatComponent {
+        let flag = |on: bool| if on { Some(true) } else { None };
+        ChatComponent {
+            text,
+            color: self.color,
+            bold: flag(self.bold),
+            italic: flag(self.italic),
+            underlined: flag(self.underlined),
+            strikethrough: flag(self.strikethrough),
+            obfuscated: flag(self.obfuscated),
+            extra: Vec::new()
+        }
+    }
+}
+
+impl fmt::Display for ChatComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_plain())
+    }
+}
+
+impl From<&str> for ChatComponent {
+    fn from(text: &str) -> Self {
+        ChatComponent::from_legacy(text)
+    }
+}
+
+fn parse_legacy_rgb<I: Iterator<Item = char>>(mut chars: I) -> Option<Color> {
+    let mut hex = String::with_capacity(6);
+    for _ in 0..6 {
+        if chars.next()? != SECTION {
+            return None;
+        }
+        hex.push(chars.next().filter(char::is_ascii_hexdigit)?);
+    }
+    Color::from_name(&format!("#{}", hex))
+}
+
+fn strip_codes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let is_code = chars.peek().is_some_and(|code| matches!(code.to_ascii_lowercase(), '0'..='9' | 'a'..='f' | 'k'..='o' | 'r' | 'x'));
+        if c == SECTION && is_code {
+            chars.next();
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// Nearest entry of the xterm palette, from either the 6x6x6 cube or the grayscale ramp.
+fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let nearest = |value: u8| {
+        (0..6).min_by_key(|i| (LEVELS[*i] as i32 - value as i32).abs()).unwrap_or(0)
+    };
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let (dr, dg, db) = (r as i32 - r2 as i32, g as i32 - g2 as i32, b as i32 - b2 as i32);
+        dr * dr + dg * dg + db * db
+    };
+
+    let (ri, gi, bi) = (nearest(r), nearest(g), nearest(b));
+    let cube = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let step = ((average.saturating_sub(8) + 5) / 10).min(23) as u8;
+    let gray = 8 + step * 10;
+
+    if distance((gray, gray, gray)) < distance(cube) {
+        232 + step
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            '\n' => out.push_str("<br>"),
+            c => out.push(c)
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn styled(text: &str, color: Option<Color>) -> ChatComponent {
+        ChatComponent { color, ..ChatComponent::text(text) }
+    }
+
+    #[test]
+    fn color_names() {
+        for (color, name, code) in NAMED_COLORS {
+            assert_eq!(Color::from_name(name), Some(color));
+            assert_eq!(color.name(), name);
+            assert_eq!(Color::from_code(code), Some(color));
+            assert_eq!(Color::from_code(code.to_ascii_uppercase()), Some(color));
+        }
+        assert_eq!(Color::from_name("reset"), Some(Color::Reset));
+        assert_eq!(Color::from_name("#FFaa00"), Some(Color::Rgb(255, 170, 0)));
+        assert_eq!(Color::Rgb(255, 170, 0).name(), "#ffaa00");
+        assert_eq!(Color::from_name("pink"), None);
+        assert_eq!(Color::Gold.rgb(), Some((255, 170, 0)));
+        assert_eq!(Color::Reset.rgb(), None);
+    }
+
+    #[test]
+    fn invalid_hex_colors() {
+        for invalid in ["#", "#fffff", "#fffffff", "#12345g", "#+fffff", "#-00001", "# fffff", "#ff\u{00e9}ff"] {
+            assert_eq!(Color::from_name(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn legacy_runs() {
+        let component = ChatComponent::from_legacy("\u{00a7}aGreen \u{00a7}lBold\u{00a7}r plain");
+        assert_eq!(component.text, "");
+        assert_eq!(component.extra, vec![
+            styled("Green ", Some(Color::Green)),
+            ChatComponent { bold: Some(true), ..styled("Bold", Some(Color::Green)) },
+            ChatComponent::text(" plain")
+        ]);
+        assert_eq!(component.to_plain(), "Green Bold plain");
+    }
+
+    #[test]
+    fn legacy_unstyled() {
+        assert_eq!(ChatComponent::from_legacy("Just text"), ChatComponent::text("Just text"));
+        assert_eq!(ChatComponent::from_legacy(""), ChatComponent::default());
+        assert_eq!(ChatComponent::from_legacy("\u{00a7}a"), ChatComponent::default());
+    }
+
+    #[test]
+    fn legacy_color_resets_formatting() {
+        let component = ChatComponent::from_legacy("\u{00a7}l\u{00a7}nBold\u{00a7}cRed");
+        assert_eq!(component.extra[1], styled("Red", Some(Color::Red)));
+    }
+
+    #[test]
+    fn legacy_uppercase_codes() {
+        let component = ChatComponent::from_legacy("\u{00a7}AGreen\u{00a7}LBold");
+        assert_eq!(component.extra[0], styled("Green", Some(Color::Green)));
+        assert_eq!(component.to_legacy(), "\u{00a7}aGreen\u{00a7}lBold");
+    }
+
+    #[test]
+    fn legacy_rgb() {
+        let component = ChatComponent::from_legacy("\u{00a7}x\u{00a7}f\u{00a7}f\u{00a7}0\u{00a7}0\u{00a7}8\u{00a7}8Pink");
+        assert_eq!(component.extra, vec![styled("Pink", Some(Color::Rgb(255, 0, 136)))]);
+        assert_eq!(component.to_legacy(), "\u{00a7}x\u{00a7}f\u{00a7}f\u{00a7}0\u{00a7}0\u{00a7}8\u{00a7}8Pink");
+    }
+
+    #[test]
+    fn legacy_short_rgb() {
+        // Too few digits: the `§x` stays text and the digits that follow act as colors.
+        let component = ChatComponent::from_legacy("\u{00a7}x\u{00a7}fWhite");
+        assert_eq!(component.extra, vec![ChatComponent::text("\u{00a7}x"), styled("White", Some(Color::White))]);
+    }
+
+    #[test]
+    fn stray_section_signs() {
+        assert_eq!(ChatComponent::from_legacy("100\u{00a7}"), ChatComponent::text("100\u{00a7}"));
+        assert_eq!(ChatComponent::from_legacy("a \u{00a7} b"), ChatComponent::text("a \u{00a7} b"));
+        assert_eq!(ChatComponent::from_legacy("\u{00a7}zed"), ChatComponent::text("\u{00a7}zed"));
+        assert_eq!(ChatComponent::from_legacy("a \u{00a7} b").to_legacy(), "a \u{00a7} b");
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        for text in [
+            "plain",
+            "\u{00a7}aGreen \u{00a7}lBold\u{00a7}r plain",
+            "\u{00a7}6\u{00a7}l\u{00a7}oGold\u{00a7}r\u{00a7}k?\u{00a7}r done",
+            "\u{00a7}cRed\u{00a7}9Blue\u{00a7}mStruck\u{00a7}nUnder",
+            "\u{00a7}x\u{00a7}1\u{00a7}2\u{00a7}3\u{00a7}4\u{00a7}5\u{00a7}6RGB\u{00a7}aGreen",
+            "Line one\n\u{00a7}eLine two"
+        ] {
+            assert_eq!(ChatComponent::from_legacy(text).to_legacy(), text);
+        }
+    }
+
+    #[test]
+    fn json_to_legacy_inherits() {
+        let component = ChatComponent::from_json(&json!({
+            "text": "a",
+            "color": "red",
+            "extra": [{ "text": "b", "bold": true }, "c", { "text": "d", "color": "reset" }]
+        }));
+        assert_eq!(component.to_legacy(), "\u{00a7}ca\u{00a7}lb\u{00a7}cc\u{00a7}rd");
+        assert_eq!(component.to_plain(), "abcd");
+    }
+
+    #[test]
+    fn json_shapes() {
+        assert_eq!(ChatComponent::from_json(&json!("text")), ChatComponent::text("text"));
+        assert_eq!(ChatComponent::from_json(&json!(5)), ChatComponent::text("5"));
+        assert_eq!(ChatComponent::from_json(&json!(true)), ChatComponent::text("true"));
+        assert_eq!(ChatComponent::from_json(&json!(null)), ChatComponent::default());
+        assert_eq!(ChatComponent::from_json(&json!({ "text": 1.5 })), ChatComponent::text("1.5"));
+        assert_eq!(ChatComponent::from_json(&json!({ "translate": "x" })), ChatComponent::default());
+
+        let array = ChatComponent::from_json(&json!(["a", { "text": "b", "color": "blue" }, "c"]));
+        assert_eq!(array.text, "a");
+        assert_eq!(array.extra, vec![styled("b", Some(Color::Blue)), ChatComponent::text("c")]);
+        assert_eq!(ChatComponent::from_json(&json!([])), ChatComponent::default());
+    }
+
+    #[test]
+    fn json_flags_and_extra() {
+        let component = ChatComponent::from_json(&json!({
+            "text": "x",
+            "bold": "true",
+            "italic": false,
+            "underlined": "yes",
+            "color": "#12345g",
+            "extra": { "text": "y" }
+        }));
+        assert_eq!(component.bold, Some(true));
+        assert_eq!(component.italic, Some(false));
+        assert_eq!(component.underlined, None);
+        assert_eq!(component.color, None);
+        assert_eq!(component.extra, vec![ChatComponent::text("y")]);
+    }
+
+    #[test]
+    fn to_json() {
+        assert_eq!(ChatComponent::text("plain").to_json(), json!("plain"));
+        let component = ChatComponent {
+            strikethrough: Some(false),
+            extra: vec![ChatComponent::text("child")],
+            ..styled("parent", Some(Color::Rgb(1, 2, 3)))
+        };
+        assert_eq!(component.to_json(), json!({
+            "text": "parent",
+            "color": "#010203",
+            "strikethrough": false,
+            "extra": ["child"]
+        }));
+        assert_eq!(ChatComponent::from_json(&component.to_json()), component);
+
+        let legacy = ChatComponent::from_legacy("\u{00a7}aGreen \u{00a7}lBold\u{00a7}r plain");
+        assert_eq!(ChatComponent::from_json(&legacy.to_json()), legacy);
+        assert_eq!(ChatComponent::from_json_str(&legacy.to_json().to_string()).unwrap(), legacy);
+    }
+
+    #[test]
+    fn plain_strips_embedded_codes() {
+        let component = ChatComponent::from_json(&json!({ "text": "\u{00a7}aHi \u{00a7}x", "extra": ["\u{00a7}lthere \u{00a7}z"] }));
+        assert_eq!(component.to_plain(), "Hi there \u{00a7}z");
+        assert_eq!(component.to_string(), "Hi there \u{00a7}z");
+    }
+
+    #[test]
+    fn ansi() {
+        let component = ChatComponent::from_legacy("\u{00a7}6\u{00a7}lGold\u{00a7}r \u{00a7}k\u{00a7}m?");
+        assert_eq!(
+            component.to_ansi(ColorDepth::TrueColor),
+            "\x1b[0;1;38;2;255;170;0mGold\x1b[0m \x1b[0;5;9m?\x1b[0m"
+        );
+        assert_eq!(
+            component.to_ansi(ColorDepth::Ansi256),
+            "\x1b[0;1;38;5;214mGold\x1b[0m \x1b[0;5;9m?\x1b[0m"
+        );
+        assert_eq!(ChatComponent::text("plain").to_ansi(ColorDepth::TrueColor), "\x1b[0mplain\x1b[0m");
+        assert_eq!(ChatComponent::default().to_ansi(ColorDepth::Ansi256), "");
+    }
+
+    #[test]
+    fn ansi256_palette() {
+        assert_eq!(ansi256(0, 0, 0), 16);
+        assert_eq!(ansi256(255, 255, 255), 231);
+        assert_eq!(ansi256(255, 0, 0), 196);
+        assert_eq!(ansi256(85, 255, 85), 83);
+        // Grays land on the grayscale ramp rather than the coarser cube.
+        assert_eq!(ansi256(128, 128, 128), 244);
+        assert_eq!(ansi256(8, 8, 8), 232);
+        assert_eq!(ansi256(238, 238, 238), 255);
+    }
+
+    #[test]
+    fn html() {
+        let component = ChatComponent::from_legacy("\u{00a7}c\u{00a7}l\u{00a7}nHi\u{00a7}r \u{00a7}o\u{00a7}m\u{00a7}kx");
+        assert_eq!(
+            component.to_html(),
+            "<span style=\"color:#ff5555;font-weight:bold;text-decoration:underline\">Hi</span> \
+             <span class=\"obfuscated\" style=\"font-style:italic;text-decoration:line-through\">x</span>"
+        );
+        assert_eq!(
+            ChatComponent::text("<b> & \"q\" 'a'\nnext").to_html(),
+            "&lt;b&gt; &amp; &quot;q&quot; &#39;a&#39;<br>next"
+        );
+        assert_eq!(ChatComponent::from_legacy("\u{00a7}x\u{00a7}0\u{00a7}1\u{00a7}0\u{00a7}2\u{00a7}0\u{00a7}3a<").to_html(), "<span style=\"color:#010203\">a&lt;</span>");
+    }
+
+    #[test]
+    fn embedded_color_resets_inherited_formatting() {
+        let component = ChatComponent::from_json(&json!({ "text": "x", "bold": true, "extra": ["\u{00a7}aGreen"] }));
+        assert_eq!(component.to_html(), "<span style=\"font-weight:bold\">x</span><span style=\"color:#55ff55\">Green</span>");
+        assert_eq!(
+            component.to_ansi(ColorDepth::TrueColor),
+            "\x1b[0;1mx\x1b[0;38;2;85;255;85mGreen\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn embedded_codes_build_on_inherited_style() {
+        // Formatting codes add to the inherited style, and `§r` returns to it.
+        let component = ChatComponent::from_json(&json!({
+            "text": "",
+            "color": "gold",
+            "italic": true,
+            "extra": ["\u{00a7}lA\u{00a7}bB\u{00a7}rC"]
+        }));
+        assert_eq!(
+            component.to_html(),
+            "<span style=\"color:#ffaa00;font-weight:bold;font-style:italic\">A</span>\
+             <span style=\"color:#55ffff\">B</span>\
+             <span style=\"color:#ffaa00;font-style:italic\">C</span>"
+        );
+        // Stray `§` signs are left alone.
+        assert_eq!(ChatComponent::from_json(&json!({ "text": "5 \u{00a7} 6", "bold": true })).to_html(), "<span style=\"font-weight:bold\">5 \u{00a7} 6</span>");
+    }
+}
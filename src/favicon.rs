@@ -0,0 +1,202 @@
+use std::fs;
+use std::io::Error as IoError;
+use std::path::Path;
+use crate::PingError;
+
+const DATA_URI_PREFIX: &str = "data:image/png;base64,";
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub const FAVICON_SIZE: u32 = 64;
+
+// A server icon: PNG bytes whose IHDR header says 64x64.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Favicon {
+    png: Vec<u8>
+}
+
+impl Favicon {
+    pub fn from_png(png: Vec<u8>) -> Result<Favicon, PingError> {
+        if png.get(..8) != Some(&PNG_SIGNATURE[..]) {
+            return Err(PingError::InvalidFavicon("not a PNG image"));
+        }
+        // The IHDR chunk always comes first: length, type, then width and height.
+        if png.get(12..16) != Some(&b"IHDR"[..]) {
+            return Err(PingError::InvalidFavicon("missing IHDR chunk"));
+        }
+        let dimension = |offset: usize| {
+            png.get(offset..offset + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        };
+        match (dimension(16), dimension(20)) {
+            (Some(FAVICON_SIZE), Some(FAVICON_SIZE)) => Ok(Favicon { png }),
+            (Some(_), Some(_)) => Err(PingError::InvalidFavicon("image is not 64x64")),
+            _ => Err(PingError::InvalidFavicon("truncated IHDR chunk"))
+        }
+    }
+
+    // Decodes the `data:image/png;base64,...` form used in status responses. Some servers
+    // wrap the base64 in line breaks, which are ignored.
+    pub fn from_data_uri(uri: &str) -> Result<Favicon, PingError> {
+        let data = uri.strip_prefix(DATA_URI_PREFIX)
+            .ok_or(PingError::InvalidFavicon("not a base64 PNG data URI"))?;
+        Favicon::from_png(decode_base64(data).ok_or(PingError::InvalidFavicon("invalid base64"))?)
+    }
+
+    pub fn to_data_uri(&self) -> String {
+        format!("{}{}", DATA_URI_PREFIX, encode_base64(&self.png))
+    }
+
+    pub fn as_png(&self) -> &[u8] {
+        &self.png
+    }
+
+    pub fn into_png(self) -> Vec<u8> {
+        self.png
+    }
+
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> Result<(), IoError> {
+        fs::write(path, &self.png)
+    }
+}
+
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in data.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            break;
+        }
+        let value = BASE64_ALPHABET.iter().position(|a| *a == c)? as u32;
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let block = chunk.iter().enumerate().fold(0u32, |block, (i, byte)| block | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(block >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The signature and IHDR chunk of a PNG; enough for `from_png`, which checks nothing more.
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&13u32.to_be_bytes());
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&width.to_be_bytes());
+        png.extend_from_slice(&height.to_be_bytes());
+        png.extend_from_slice(&[8, 6, 0, 0, 0]);
+        png.extend_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        png
+    }
+
+    fn error(result: Result<Favicon, PingError>) -> &'static str {
+        match result {
+            Err(PingError::InvalidFavicon(reason)) => reason,
+            other => panic!("unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn valid_png() {
+        let favicon = Favicon::from_png(png(64, 64)).unwrap();
+        assert_eq!(favicon.as_png(), &png(64, 64)[..]);
+        assert_eq!(favicon.into_png(), png(64, 64));
+    }
+
+    #[test]
+    fn invalid_png() {
+        assert_eq!(error(Favicon::from_png(Vec::new())), "not a PNG image");
+        assert_eq!(error(Favicon::from_png(b"GIF89a\0\0\0\0\0\0".to_vec())), "not a PNG image");
+
+        let mut no_ihdr = png(64, 64);
+        no_ihdr[12..16].copy_from_slice(b"IDAT");
+        assert_eq!(error(Favicon::from_png(no_ihdr)), "missing IHDR chunk");
+        assert_eq!(error(Favicon::from_png(PNG_SIGNATURE.to_vec())), "missing IHDR chunk");
+
+        assert_eq!(error(Favicon::from_png(png(64, 32))), "image is not 64x64");
+        assert_eq!(error(Favicon::from_png(png(128, 128))), "image is not 64x64");
+
+        assert_eq!(error(Favicon::from_png(png(64, 64)[..22].to_vec())), "truncated IHDR chunk");
+        assert_eq!(error(Favicon::from_png(png(64, 64)[..17].to_vec())), "truncated IHDR chunk");
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert_eq!(decode_base64("QQ==").unwrap(), b"A");
+        assert_eq!(decode_base64("QQ").unwrap(), b"A");
+        assert_eq!(decode_base64("QUI=").unwrap(), b"AB");
+        assert_eq!(decode_base64("QUJD").unwrap(), b"ABC");
+        assert_eq!(decode_base64("Q U\r\nJ\tD\n").unwrap(), b"ABC");
+        assert_eq!(decode_base64("+/+/").unwrap(), [0xFB, 0xFF, 0xBF]);
+
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"A"), "QQ==");
+        assert_eq!(encode_base64(b"AB"), "QUI=");
+        assert_eq!(encode_base64(b"ABC"), "QUJD");
+        assert_eq!(encode_base64(&[0xFB, 0xFF, 0xBF]), "+/+/");
+    }
+
+    #[test]
+    fn invalid_base64() {
+        for invalid in ["QQ!=", "-_-_", "QUJ\u{00e9}", "QU.D"] {
+            assert_eq!(decode_base64(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn data_uri() {
+        let favicon = Favicon::from_png(png(64, 64)).unwrap();
+        let uri = favicon.to_data_uri();
+        assert!(uri.starts_with("data:image/png;base64,iVBORw0KGgo"));
+        assert_eq!(Favicon::from_data_uri(&uri).unwrap(), favicon);
+
+        // Line-wrapped base64, as some servers send it.
+        let data = &uri[DATA_URI_PREFIX.len()..];
+        let wrapped = format!("{}{}\n{}", DATA_URI_PREFIX, &data[..20], &data[20..]);
+        assert_eq!(Favicon::from_data_uri(&wrapped).unwrap(), favicon);
+    }
+
+    #[test]
+    fn invalid_data_uri() {
+        let data = encode_base64(&png(64, 64));
+        assert_eq!(error(Favicon::from_data_uri(&format!("data:image/jpeg;base64,{}", data))), "not a base64 PNG data URI");
+        assert_eq!(error(Favicon::from_data_uri(&data)), "not a base64 PNG data URI");
+        assert_eq!(error(Favicon::from_data_uri("data:image/png;base64,iVBO*")), "invalid base64");
+        assert_eq!(error(Favicon::from_data_uri(&format!("{}{}", DATA_URI_PREFIX, encode_base64(&png(16, 16))))), "image is not 64x64");
+    }
+
+    #[test]
+    fn write_to() {
+        let favicon = Favicon::from_png(png(64, 64)).unwrap();
+        let path = std::env::temp_dir().join(format!("pinger-favicon-{}.png", std::process::id()));
+        favicon.write_to(&path).unwrap();
+        let written = fs::read(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written.unwrap(), png(64, 64));
+
+        assert!(favicon.write_to(std::env::temp_dir().join("pinger-missing-dir").join("icon.png")).is_err());
+    }
+}
@@ -1,73 +1,432 @@
+//! Pings Minecraft servers of every era, and Bedrock, Query and RCON endpoints.
+//!
+//! # JSON schema
+//!
+//! With the `serde` feature, the status types serialize to the JSON below, which is meant to be
+//! stored and read back by other tools. It only changes with the major version.
+//!
+//! ```text
+//! Status        { "dirty": bool, "version": Version | null, "motd": chat, "favicon": string | null,
+//!                 "players": Players, "modded": ModdedInfo | null }
+//! Version       { "protocol": int, "server": string }
+//! Players       { "online": int, "max": int, "sample": [PlayerSample] }
+//! PlayerSample  { "name": string, "id": uuid }
+//! ModdedInfo    { "loader": "fml1" | "fml2" | "fml3", "mods": [Mod], "channels": [Channel], "truncated": bool }
+//! Mod           { "id": string, "version": string }
+//! Channel       { "name": string, "version": string, "required": bool }
+//! PingProtocol  "beta" | "legacy14" | "legacy16" | "modern"
+//! BedrockStatus { "edition": string, "motd": chat, "version": Version, "players": Players,
+//!                 "server_id": int, "sub_motd": chat | null, "game_mode": string | null,
+//!                 "port_v4": int | null, "port_v6": int | null }
+//! BasicStat     { "motd": chat, "game_type": string, "map": string, "players": Players,
+//!                 "host_port": int, "host_ip": string }
+//! FullStat      { "motd": chat, "game_type": string, "game_id": string, "version": string,
+//!                 "software": string, "plugins": [Plugin], "map": string, "players": Players,
+//!                 "player_names": [string], "host_port": int, "host_ip": string }
+//! Plugin        { "name": string, "version": string }
+//! ```
+//!
+//! Chat components use the vanilla JSON text format (see `ChatComponent::to_json`) and colors their
+//! vanilla names or `#rrggbb`. Favicons are `data:image/png;base64,...` URIs and UUIDs are
+//! hyphenated lowercase hex. Deserializing validates favicons and UUIDs like parsing does.
+
+use std::fmt;
 use std::io::{Read, Write};
 use std::io::{Error as IoError, ErrorKind as IoErrorKind};
-use std::net::{SocketAddr, TcpStream};
-use std::num::ParseIntError;
-use std::time::Duration;
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use byteorder::{WriteBytesExt, ReadBytesExt, BE};
 use thiserror::Error;
 
-pub fn get_status(address: &SocketAddr, timeout: Duration) -> Result<Status, PingError> {
-    let mut stream = TcpStream::connect_timeout(address, timeout)?;
-    stream.set_read_timeout(Some(Duration::from_millis(500)))?;
-    stream.write_all(&[0xFE, 0x01])?;
-
-    let packet_id = stream.read_u8()?;
-
-    if packet_id == 0xFF {
-        let response = stream.read_utf16_string()?;
-        if response.starts_with("\u{00a7}1") {
-            let status: Vec<&str> = response.split('\u{0}').collect();
-            Ok(Status {
-                dirty: true,
-                version: Some(Version {
-                    protocol: status[1].parse::<i16>()?,
-                    server: String::from(status[2])
-                }),
-                motd: String::from(status[3]),
-                online: (
-                    status[4].parse::<u16>()?,
-                    status[5].parse::<u16>()?
-                )
-            })
-        } else {
-            let status: Vec<&str> = response.split('\u{00a7}').collect();
-            Ok(Status {
-                dirty: true,
-                version: None,
-                motd: String::from(status[0]),
-                online: (
-                    status[1].parse::<u16>()?,
-                    status[2].parse::<u16>()?
-                )
-            })
+mod address;
+mod batch;
+mod bedrock;
+mod chat;
+mod favicon;
+mod modded;
+mod options;
+mod players;
+mod query;
+mod rcon;
+mod responder;
+mod scanner;
+#[cfg(feature = "serde")]
+mod schema;
+mod session;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+
+pub use address::{Resolver, ResolvedAddress, ServerAddress, SystemResolver, DEFAULT_PORT};
+pub use batch::{ping_many, BatchOptions, PingMany};
+pub use bedrock::{get_bedrock_status, BedrockStatus, BEDROCK_DEFAULT_PORT};
+pub use chat::{ChatComponent, Color, ColorDepth};
+pub use favicon::{Favicon, FAVICON_SIZE};
+pub use modded::{Channel, Mod, ModLoader, ModdedInfo, SERVER_ONLY_VERSION};
+pub use options::{PingOptions, TimeoutPhase};
+pub use players::{PlayerSample, Players, Uuid};
+pub use query::{query_basic, query_full, BasicStat, FullStat, Plugin};
+pub use rcon::{Rcon, RCON_DEFAULT_PORT};
+pub use responder::{respond, StatusResponder};
+pub use scanner::{scan, Cidr, Scan, ScanOptions};
+pub use session::{Outcome, Session};
+use options::{timed_out, Timer};
+
+// Protocol version sent in the modern handshake. Servers answer status requests regardless of it.
+const PROTOCOL_VERSION: i32 = 47;
+// Protocol version sent in the 1.6 MC|PingHost plugin message (1.6.4).
+const LEGACY_PROTOCOL_VERSION: u8 = 78;
+// Protocol version vanilla advertises to legacy clients when it has nothing better to say.
+const LEGACY_FALLBACK_PROTOCOL: i32 = 127;
+// Order in which `ping_auto` tries the protocols.
+const FALLBACK_ORDER: [PingProtocol; 4] = [PingProtocol::Modern, PingProtocol::Legacy16, PingProtocol::Legacy14, PingProtocol::Beta];
+// Largest packet length representable by a 3-byte VarInt.
+const MAX_PACKET_LENGTH: i32 = 2097151;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum PingProtocol {
+    // Beta 1.8 to 1.3: a bare 0xFE, answered with `motd§online§max`.
+    Beta,
+    // 1.4 to 1.5: 0xFE 0x01, answered with `§1\0protocol\0version\0motd\0online\0max`.
+    Legacy14,
+    // 1.6: 0xFE 0x01 followed by an MC|PingHost plugin message, same answer as 1.4.
+    Legacy16,
+    // 1.7+: handshake and status request over VarInt-framed packets, answered with JSON.
+    Modern
+}
+
+impl fmt::Display for PingProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PingProtocol::Beta => "beta",
+            PingProtocol::Legacy14 => "legacy14",
+            PingProtocol::Legacy16 => "legacy16",
+            PingProtocol::Modern => "modern"
+        })
+    }
+}
+
+pub fn get_status<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<Status, PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect(address, &timer)?;
+    Ok(drive(&mut stream, Session::any_legacy(), &timer)?.status)
+}
+
+pub fn get_status_with<O: Into<PingOptions>>(address: &SocketAddr, protocol: PingProtocol, options: O) -> Result<Status, PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect(address, &timer)?;
+    let session = Session::new(protocol, &address.ip().to_string(), address.port())?;
+    Ok(drive(&mut stream, session, &timer)?.status)
+}
+
+// Tries every protocol from newest to oldest on a fresh connection, returning the first that answers.
+// Connection failures are returned immediately; a reset or malformed reply moves on to the next era.
+pub fn ping_auto<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<(Status, PingProtocol), PingError> {
+    let timer = Timer::start(options.into());
+    let (outcome, protocol) = ping_first(&[*address], &address.ip().to_string(), address.port(), &FALLBACK_ORDER, false, &timer)?;
+    Ok((outcome.status, protocol))
+}
+
+// Like `ping_auto`, but only tries `protocols` (in the given order) and returns the whole outcome.
+// A modern answer is followed by a Ping so that the outcome carries the latency.
+pub fn ping_resolved<O: Into<PingOptions>>(address: &ResolvedAddress, protocols: &[PingProtocol], options: O) -> Result<(Outcome, PingProtocol), PingError> {
+    let timer = Timer::start(options.into());
+    ping_first(&address.addresses, &address.hostname, address.port, protocols, true, &timer)
+}
+
+fn ping_first(addresses: &[SocketAddr], hostname: &str, port: u16, protocols: &[PingProtocol], measure_latency: bool, timer: &Timer) -> Result<(Outcome, PingProtocol), PingError> {
+    let mut last_error = None;
+
+    for &protocol in protocols {
+        let mut stream = connect_any(addresses, timer)?;
+        match drive(&mut stream, Session::new(protocol, hostname, port)?.measure_latency(measure_latency), timer) {
+            Ok(outcome) => return Ok((outcome, protocol)),
+            Err(e) if e.is_protocol_mismatch() => last_error = Some(e),
+            Err(e) => return Err(e)
         }
-    } else {
-        Err(PingError::UnexpectedPacketId(packet_id))
     }
+
+    Err(last_error.unwrap_or_else(no_protocols))
+}
+
+pub fn get_status_host<O: Into<PingOptions>>(address: &ServerAddress, protocol: PingProtocol, options: O) -> Result<Status, PingError> {
+    get_status_resolved(&address.resolve(&SystemResolver::default())?, protocol, options)
+}
+
+pub fn get_status_resolved<O: Into<PingOptions>>(address: &ResolvedAddress, protocol: PingProtocol, options: O) -> Result<Status, PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect_any(&address.addresses, &timer)?;
+    Ok(drive(&mut stream, Session::new(protocol, &address.hostname, address.port)?, &timer)?.status)
+}
+
+pub fn get_status_legacy16<O: Into<PingOptions>>(address: &SocketAddr, hostname: &str, port: u16, options: O) -> Result<Status, PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect(address, &timer)?;
+    Ok(drive(&mut stream, Session::new(PingProtocol::Legacy16, hostname, port)?, &timer)?.status)
+}
+
+pub fn get_status_modern<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<Status, PingError> {
+    get_status_with(address, PingProtocol::Modern, options)
+}
+
+pub fn get_status_and_latency<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<(Status, Duration), PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect(address, &timer)?;
+    let session = Session::new(PingProtocol::Modern, &address.ip().to_string(), address.port())?
+        .measure_latency(true);
+    let outcome = drive(&mut stream, session, &timer)?;
+    Ok((outcome.status, outcome.latency.unwrap_or_default()))
+}
+
+fn connect(address: &SocketAddr, timer: &Timer) -> Result<TcpStream, PingError> {
+    let (timeout, phase) = timer.connect()?;
+    TcpStream::connect_timeout(address, timeout).map_err(|e| timed_out(e, phase))
+}
+
+// Tries each address in turn, like `TcpStream::connect` does for multiple resolved addresses.
+fn connect_any(addresses: &[SocketAddr], timer: &Timer) -> Result<TcpStream, PingError> {
+    let mut last_error = None;
+    for address in addresses {
+        match connect(address, timer) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e)
+        }
+    }
+    Err(last_error.unwrap_or_else(no_addresses))
+}
+
+// Runs the session to its outcome, which may be a status whose Pong never arrived.
+fn drive(stream: &mut TcpStream, mut session: Session, timer: &Timer) -> Result<Outcome, PingError> {
+    exchange(stream, &mut session, timer).or_else(|e| session.handle_close().ok_or(e))
+}
+
+// The socket timeouts are set before every call, since each is cut short by the time left
+// until the deadline.
+fn exchange(stream: &mut TcpStream, session: &mut Session, timer: &Timer) -> Result<Outcome, PingError> {
+    let mut buffer = [0u8; 4096];
+    loop {
+        while let Some(data) = session.poll_transmit(Instant::now()) {
+            let (timeout, phase) = timer.write()?;
+            stream.set_write_timeout(Some(timeout))?;
+            stream.write_all(&data).map_err(|e| timed_out(e, phase))?;
+        }
+        let (timeout, phase) = timer.read()?;
+        stream.set_read_timeout(Some(timeout))?;
+        let len = stream.read(&mut buffer).map_err(|e| timed_out(e, phase))?;
+        if len == 0 {
+            return Err(connection_closed());
+        }
+        if let Some(outcome) = session.handle_input(&buffer[..len], Instant::now())? {
+            return Ok(outcome);
+        }
+    }
+}
+
+fn no_addresses() -> PingError {
+    IoError::new(IoErrorKind::InvalidInput, "No addresses to connect to").into()
+}
+
+fn no_protocols() -> PingError {
+    IoError::new(IoErrorKind::InvalidInput, "No protocols to try").into()
+}
+
+fn connection_closed() -> PingError {
+    PingError::ConnectionReset(IoError::new(IoErrorKind::UnexpectedEof, "Connection closed by the server"))
+}
+
+// A UDP socket on an ephemeral port of the same family as `address`. Connecting filters out
+// datagrams from anyone else.
+pub(crate) fn udp_connect(address: &SocketAddr) -> Result<UdpSocket, PingError> {
+    let local: SocketAddr = match address {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(address)?;
+    Ok(socket)
+}
+
+// Milliseconds since the epoch, as vanilla and RakNet put in their pings.
+pub(crate) fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+// Parses one field of a response, blaming the whole response if it does not parse.
+pub(crate) fn parse_field<T: FromStr>(value: &str, field: &'static str, response: &str) -> Result<T, PingError> {
+    value.parse::<T>().map_err(|_| PingError::malformed(field, response))
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Version {
-    pub protocol: i16,
+    pub protocol: i32,
     pub server: String
 }
 
-#[derive(Debug, Clone, PartialOrd, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Status {
     pub dirty: bool,
     pub version: Option<Version>,
-    pub motd: String,
-    pub online: (u16, u16)
+    pub motd: ChatComponent,
+    pub favicon: Option<Favicon>,
+    pub players: Players,
+    pub modded: Option<ModdedInfo>
+}
+
+impl Status {
+    // The 0xFF kick packet a server of the given legacy era answers a ping with. Only what the
+    // era can carry survives: the version is dropped for beta (and written as protocol 127 when
+    // missing for 1.4+), beta MOTDs lose their formatting, and the sample, favicon and mod info go.
+    // The modern era has no kick packet and is rejected as invalid input.
+    pub fn encode_legacy(&self, era: PingProtocol) -> Result<Vec<u8>, PingError> {
+        // Both eras use a separator that may not appear in the fields.
+        let response = match era {
+            PingProtocol::Beta => format!(
+                "{}\u{00a7}{}\u{00a7}{}",
+                self.motd.to_plain().replace('\u{00a7}', ""), self.players.online, self.players.max
+            ),
+            PingProtocol::Legacy14 | PingProtocol::Legacy16 => {
+                let (protocol, server) = match &self.version {
+                    Some(version) => (version.protocol, version.server.replace('\0', "")),
+                    None => (LEGACY_FALLBACK_PROTOCOL, String::new())
+                };
+                format!(
+                    "\u{00a7}1\0{}\0{}\0{}\0{}\0{}",
+                    protocol, server, self.motd.to_legacy().replace('\0', ""), self.players.online, self.players.max
+                )
+            }
+            PingProtocol::Modern => {
+                return Err(IoError::new(IoErrorKind::InvalidInput, "Modern status is not sent as a kick packet").into())
+            }
+        };
+
+        let length = response.encode_utf16().count();
+        if length > u16::MAX as usize {
+            return Err(IoError::new(IoErrorKind::InvalidInput, "Legacy response too long").into());
+        }
+        let mut packet = Vec::with_capacity(3 + 2 * length);
+        packet.write_u8(0xFF)?;
+        packet.write_utf16_string(response)?;
+        Ok(packet)
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum PingError {
+    // Nothing is listening on the port.
+    #[error("Connection refused")]
+    ConnectionRefused(#[source] IoError),
+    // The phase that ran out of time, and the error the socket reported. An expired deadline
+    // carries a synthesized `TimedOut` error.
     #[error("{0}")]
-    Io(#[from] IoError),
-    #[error("{0}")]
-    ParseInt(#[from] ParseIntError),
+    Timeout(TimeoutPhase, #[source] IoError),
+    // The server closed or reset the connection before its response was complete.
+    #[error("Connection lost mid-response")]
+    ConnectionReset(#[source] IoError),
+    #[error("Invalid VarInt")]
+    InvalidVarInt,
+    #[error("Invalid JSON in status response: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    // The server answered in the format of another era than the one asked for.
+    #[error("Server does not support the {0} protocol")]
+    UnsupportedProtocol(PingProtocol),
+    // The server took the handshake for a login attempt, kicking with this reason if it gave one.
+    #[error("Server only accepts logins")]
+    LoginOnly(Option<ChatComponent>),
+    // RCON rejected the password.
+    #[error("Authentication failed")]
+    AuthenticationFailed,
     #[error("Unexpected packet id: {0}")]
-    UnexpectedPacketId(u8),
+    UnexpectedPacketId(i32),
+    #[error("Pong payload does not match ping: {0}")]
+    PongMismatch(i64),
+    #[error("Invalid favicon: {0}")]
+    InvalidFavicon(&'static str),
+    #[error("Invalid server address: {0}")]
+    InvalidAddress(String),
+    #[error("Malformed {field} in status response: {raw:?}")]
+    MalformedResponse {
+        field: &'static str,
+        raw: String
+    },
+    // Any other I/O failure.
+    #[error("I/O error")]
+    Io(#[source] IoError)
+}
+
+impl PingError {
+    pub(crate) fn malformed(field: &'static str, raw: &str) -> PingError {
+        PingError::MalformedResponse { field, raw: raw.to_owned() }
+    }
+
+    // Whether the same ping may well succeed if tried again: the network or the server was
+    // momentarily at fault, rather than the address or what the server speaks.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            PingError::Timeout(..) | PingError::ConnectionReset(_) => true,
+            PingError::Io(e) => matches!(
+                e.kind(),
+                IoErrorKind::Interrupted
+                    | IoErrorKind::TimedOut
+                    | IoErrorKind::WouldBlock
+                    | IoErrorKind::NetworkUnreachable
+                    | IoErrorKind::HostUnreachable
+                    | IoErrorKind::NetworkDown
+                    | IoErrorKind::AddrNotAvailable
+            ),
+            _ => false
+        }
+    }
+
+    // The I/O error behind this one, if there is one.
+    pub fn io_error(&self) -> Option<&IoError> {
+        match self {
+            PingError::ConnectionRefused(e)
+                | PingError::Timeout(_, e)
+                | PingError::ConnectionReset(e)
+                | PingError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+
+    // Whether the server reset, stalled or answered garbage, which is how servers react to a ping
+    // from an era they do not understand.
+    fn is_protocol_mismatch(&self) -> bool {
+        match self {
+            PingError::ConnectionReset(_) => true,
+            PingError::Timeout(phase, _) => matches!(phase, TimeoutPhase::Write | TimeoutPhase::Read),
+            PingError::Io(e) => matches!(
+                e.kind(),
+                IoErrorKind::UnexpectedEof | IoErrorKind::InvalidData | IoErrorKind::InvalidInput
+            ),
+            PingError::ConnectionRefused(_) | PingError::LoginOnly(_) => false,
+            _ => true
+        }
+    }
+}
+
+// Sorts I/O errors into the connection categories. `PingError`s that had to travel as an
+// `io::Error`, such as from the `PingRead` methods, come back out as themselves.
+impl From<IoError> for PingError {
+    fn from(e: IoError) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<PingError>()) {
+            let inner = e.into_inner().expect("checked above");
+            return *inner.downcast::<PingError>().expect("checked above");
+        }
+        match e.kind() {
+            IoErrorKind::ConnectionRefused => PingError::ConnectionRefused(e),
+            IoErrorKind::ConnectionReset | IoErrorKind::ConnectionAborted | IoErrorKind::BrokenPipe => {
+                PingError::ConnectionReset(e)
+            }
+            _ => PingError::Io(e)
+        }
+    }
 }
 
 pub trait PingRead: ReadBytesExt {
@@ -83,7 +442,7 @@ pub trait PingRead: ReadBytesExt {
         }
 
         // The number is too large to represent in a 32-bit value.
-        Err(IoError::new(IoErrorKind::InvalidInput, "VarInt too big"))
+        Err(IoError::new(IoErrorKind::InvalidData, PingError::InvalidVarInt))
     }
 
     fn read_var_i64(&mut self) -> Result<i64, IoError> {
@@ -98,7 +457,7 @@ pub trait PingRead: ReadBytesExt {
         }
 
         // The number is too large to represent in a 64-bit value.
-        Err(IoError::new(IoErrorKind::InvalidInput, "VarLong too big"))
+        Err(IoError::new(IoErrorKind::InvalidData, PingError::InvalidVarInt))
     }
 
     fn read_utf16_string(&mut self) -> Result<String, IoError> {
@@ -109,6 +468,26 @@ pub trait PingRead: ReadBytesExt {
         }
         Ok(String::from_utf16_lossy(&chars))
     }
+
+    fn read_utf8_string(&mut self) -> Result<String, IoError> {
+        let len = self.read_var_i32()?;
+        if !(0..=MAX_PACKET_LENGTH).contains(&len) {
+            return Err(IoError::new(IoErrorKind::InvalidData, "String length out of range"));
+        }
+        let mut bytes = vec![0u8; len as usize];
+        self.read_exact(&mut bytes)?;
+        String::from_utf8(bytes).map_err(|e| IoError::new(IoErrorKind::InvalidData, e))
+    }
+
+    fn read_packet(&mut self) -> Result<Vec<u8>, IoError> {
+        let len = self.read_var_i32()?;
+        if !(0..=MAX_PACKET_LENGTH).contains(&len) {
+            return Err(IoError::new(IoErrorKind::InvalidData, "Packet length out of range"));
+        }
+        let mut packet = vec![0u8; len as usize];
+        self.read_exact(&mut packet)?;
+        Ok(packet)
+    }
 }
 
 impl<R: Read> PingRead for R {}
@@ -153,6 +532,219 @@ pub trait PingWrite: WriteBytesExt {
         }
         Ok(())
     }
+
+    fn write_utf8_string<S>(&mut self, value: S) -> Result<(), IoError> where S: AsRef<str> {
+        let bytes = value.as_ref().as_bytes();
+
+        self.write_var_i32(bytes.len() as i32)?;
+        self.write_all(bytes)
+    }
+
+    fn write_packet(&mut self, packet: &[u8]) -> Result<(), IoError> {
+        let mut frame = Vec::with_capacity(packet.len() + 5);
+        frame.write_var_i32(packet.len() as i32)?;
+        frame.extend_from_slice(packet);
+        self.write_all(&frame)
+    }
 }
 
-impl<W: Write> PingWrite for W {}
\ No newline at end of file
+impl<W: Write> PingWrite for W {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> Status {
+        Status {
+            dirty: true,
+            version: Some(Version { protocol: 78, server: String::from("1.6.4") }),
+            motd: ChatComponent::from_legacy("\u{00a7}aA \u{00a7}lMinecraft\u{00a7}r Server"),
+            favicon: None,
+            players: Players::new(4, 20),
+            modded: None
+        }
+    }
+
+    fn round_trip(status: &Status, era: PingProtocol) -> Status {
+        let packet = status.encode_legacy(era).unwrap();
+        let mut session = Session::new(era, "localhost", 25565).unwrap();
+        let outcome = session.handle_input(&packet, Instant::now()).unwrap().unwrap();
+        assert!(session.is_finished());
+        outcome.status
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        for era in [PingProtocol::Legacy14, PingProtocol::Legacy16] {
+            assert_eq!(round_trip(&status(), era), status());
+        }
+    }
+
+    #[test]
+    fn beta_drops_formatting_and_version() {
+        let decoded = round_trip(&status(), PingProtocol::Beta);
+        assert_eq!(decoded.version, None);
+        assert_eq!(decoded.motd, ChatComponent::from_legacy("A Minecraft Server"));
+        assert_eq!(decoded.players, Players::new(4, 20));
+    }
+
+    #[test]
+    fn beta_strips_separator() {
+        let status = Status { motd: ChatComponent::from_legacy("Fish \u{00a7} Chips"), ..status() };
+        let decoded = round_trip(&status, PingProtocol::Beta);
+        assert_eq!(decoded.motd.to_plain(), "Fish  Chips");
+        assert_eq!(decoded.players, Players::new(4, 20));
+    }
+
+    #[test]
+    fn missing_version() {
+        let status = Status { version: None, ..status() };
+        for era in [PingProtocol::Legacy14, PingProtocol::Legacy16] {
+            let decoded = round_trip(&status, era);
+            assert_eq!(decoded.version, Some(Version { protocol: LEGACY_FALLBACK_PROTOCOL, server: String::new() }));
+            assert_eq!(decoded.motd, status.motd);
+        }
+    }
+
+    #[test]
+    fn nul_stripped() {
+        let status = Status {
+            version: Some(Version { protocol: 78, server: String::from("1.6\0.4") }),
+            motd: ChatComponent::from_legacy("A\0 server"),
+            ..status()
+        };
+        for era in [PingProtocol::Legacy14, PingProtocol::Legacy16] {
+            let decoded = round_trip(&status, era);
+            assert_eq!(decoded.version, Some(Version { protocol: 78, server: String::from("1.6.4") }));
+            assert_eq!(decoded.motd.to_plain(), "A server");
+            assert_eq!(decoded.players, Players::new(4, 20));
+        }
+    }
+
+    // Answers the status request, then hangs up instead of answering the Ping.
+    #[test]
+    fn server_closes_before_pong() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_packet().unwrap();
+            stream.read_packet().unwrap();
+            let mut response = Vec::new();
+            response.write_var_i32(0x00).unwrap();
+            response.write_utf8_string(r#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":3}}"#).unwrap();
+            stream.write_packet(&response).unwrap();
+            let mut ping = [0u8; 10];
+            stream.read_exact(&mut ping).unwrap();
+        });
+
+        let resolved = ServerAddress::from(address).resolve(&SystemResolver::default()).unwrap();
+        let (outcome, protocol) = ping_resolved(&resolved, &FALLBACK_ORDER, Duration::from_secs(2)).unwrap();
+        assert_eq!(protocol, PingProtocol::Modern);
+        assert_eq!(outcome.status.players, Players::new(3, 20));
+        assert_eq!(outcome.latency, None);
+    }
+
+    #[test]
+    fn io_error_kinds() {
+        let from = |kind| PingError::from(IoError::from(kind));
+        assert!(matches!(from(IoErrorKind::ConnectionRefused), PingError::ConnectionRefused(_)));
+        for kind in [IoErrorKind::ConnectionReset, IoErrorKind::ConnectionAborted, IoErrorKind::BrokenPipe] {
+            assert!(matches!(from(kind), PingError::ConnectionReset(_)), "{:?}", kind);
+        }
+        for kind in [IoErrorKind::TimedOut, IoErrorKind::UnexpectedEof, IoErrorKind::InvalidData] {
+            assert!(matches!(from(kind), PingError::Io(ref e) if e.kind() == kind), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn invalid_var_int_round_trip() {
+        let error = (&[0xFFu8; 6][..]).read_var_i32().unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::InvalidData);
+        assert!(matches!(PingError::from(error), PingError::InvalidVarInt));
+
+        let error = (&[0xFFu8; 11][..]).read_var_i64().unwrap_err();
+        assert!(matches!(PingError::from(error), PingError::InvalidVarInt));
+    }
+
+    #[test]
+    fn refused_reset_and_timed_out() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let options = PingOptions::from(Duration::from_secs(2)).read_timeout(Duration::from_millis(200));
+
+        // Connected but never accepted, so nothing answers.
+        let error = get_status_with(&address, PingProtocol::Modern, options).unwrap_err();
+        assert!(matches!(error, PingError::Timeout(TimeoutPhase::Read, _)), "{:?}", error);
+        assert!(error.is_retryable());
+
+        // Closing with the request still unread makes the kernel answer with a reset. The first
+        // connection is the one left over from above; the listener goes away after the second.
+        std::thread::spawn(move || {
+            drop(listener.accept().unwrap());
+            let (stream, _) = listener.accept().unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            drop(stream);
+        });
+        let error = get_status_with(&address, PingProtocol::Modern, options).unwrap_err();
+        assert!(matches!(error, PingError::ConnectionReset(_)), "{:?}", error);
+        assert!(error.is_retryable());
+
+        std::thread::sleep(Duration::from_millis(300));
+        let error = get_status_with(&address, PingProtocol::Modern, options).unwrap_err();
+        assert!(matches!(error, PingError::ConnectionRefused(_)), "{:?}", error);
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn retryable() {
+        let io = |kind| IoError::from(kind);
+        let retryable = [
+            PingError::Timeout(TimeoutPhase::Connect, io(IoErrorKind::TimedOut)),
+            PingError::Timeout(TimeoutPhase::Deadline, io(IoErrorKind::TimedOut)),
+            PingError::ConnectionReset(io(IoErrorKind::ConnectionReset)),
+            PingError::Io(io(IoErrorKind::Interrupted)),
+            PingError::Io(io(IoErrorKind::HostUnreachable))
+        ];
+        for error in &retryable {
+            assert!(error.is_retryable(), "{:?}", error);
+        }
+
+        let permanent = [
+            PingError::ConnectionRefused(io(IoErrorKind::ConnectionRefused)),
+            PingError::Io(io(IoErrorKind::InvalidData)),
+            PingError::InvalidVarInt,
+            PingError::UnsupportedProtocol(PingProtocol::Beta),
+            PingError::LoginOnly(None),
+            PingError::AuthenticationFailed,
+            PingError::UnexpectedPacketId(0x02),
+            PingError::PongMismatch(1),
+            PingError::InvalidFavicon("not a PNG"),
+            PingError::InvalidAddress(String::from("example.com:x")),
+            PingError::malformed("players", "{}")
+        ];
+        for error in &permanent {
+            assert!(!error.is_retryable(), "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn io_error() {
+        let error = PingError::Timeout(TimeoutPhase::Read, IoError::from(IoErrorKind::WouldBlock));
+        assert_eq!(error.io_error().map(IoError::kind), Some(IoErrorKind::WouldBlock));
+        assert_eq!(error.to_string(), "Timed out waiting for the response");
+        assert_eq!(PingError::from(IoError::from(IoErrorKind::BrokenPipe)).io_error().map(IoError::kind), Some(IoErrorKind::BrokenPipe));
+        assert!(PingError::InvalidVarInt.io_error().is_none());
+    }
+
+    #[test]
+    fn modern_rejected() {
+        assert!(matches!(status().encode_legacy(PingProtocol::Modern), Err(PingError::Io(e)) if e.kind() == IoErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn too_long() {
+        let status = Status { motd: ChatComponent::from_legacy(&"x".repeat(u16::MAX as usize)), ..status() };
+        assert!(matches!(status.encode_legacy(PingProtocol::Legacy16), Err(PingError::Io(e)) if e.kind() == IoErrorKind::InvalidInput));
+    }
+}
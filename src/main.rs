@@ -0,0 +1,233 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+use serde_json::{json, Value};
+use pinger::{ping_resolved, ColorDepth, Outcome, PingError, PingOptions, PingProtocol, ServerAddress, SystemResolver};
+
+const USAGE: &str = "\
+usage: pinger [options] <host[:port]>...
+
+options:
+  --json                    print one JSON object per server instead of text
+  --timeout <duration>      time limit for each step of a ping, e.g. 3s or 500ms (default 3s);
+                            each protocol tried adds one such limit to the server's total
+  --protocol <protocol>     legacy, modern or auto (default auto)
+  --watch <interval>        ping again every interval until interrupted
+  -h, --help                print this help";
+
+const MODERN: [PingProtocol; 1] = [PingProtocol::Modern];
+const LEGACY: [PingProtocol; 3] = [PingProtocol::Legacy16, PingProtocol::Legacy14, PingProtocol::Beta];
+const AUTO: [PingProtocol; 4] = [PingProtocol::Modern, PingProtocol::Legacy16, PingProtocol::Legacy14, PingProtocol::Beta];
+
+struct Args {
+    targets: Vec<ServerAddress>,
+    json: bool,
+    options: PingOptions,
+    protocols: &'static [PingProtocol],
+    watch: Option<Duration>
+}
+
+type PingResult = Result<(Outcome, PingProtocol), PingError>;
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("pinger: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let depth = color_depth(args.json);
+
+    let mut first = true;
+    loop {
+        let results = ping_all(&args);
+        for (target, result) in args.targets.iter().zip(&results) {
+            if args.json {
+                println!("{}", to_json(target, result));
+            } else {
+                if !first {
+                    println!();
+                }
+                print_text(target, result, depth);
+            }
+            first = false;
+        }
+
+        match args.watch {
+            Some(interval) => thread::sleep(interval),
+            None if results.iter().all(Result::is_ok) => return ExitCode::SUCCESS,
+            None => return ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Args>, String> {
+    let mut timeout = Duration::from_secs(3);
+    let mut parsed = Args {
+        targets: Vec::new(),
+        json: false,
+        options: PingOptions::new(),
+        protocols: &AUTO,
+        watch: None
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--json" => parsed.json = true,
+            "--timeout" => timeout = parse_duration(&value("--timeout")?)?,
+            "--watch" => parsed.watch = Some(parse_duration(&value("--watch")?)?),
+            "--protocol" => {
+                parsed.protocols = match value("--protocol")?.as_str() {
+                    "legacy" => &LEGACY,
+                    "modern" => &MODERN,
+                    "auto" => &AUTO,
+                    other => return Err(format!("unknown protocol '{}', expected legacy, modern or auto", other))
+                }
+            }
+            option if option.starts_with('-') => return Err(format!("unknown option '{}'", option)),
+            target => parsed.targets.push(target.parse().map_err(|e: PingError| e.to_string())?)
+        }
+    }
+
+    if parsed.targets.is_empty() {
+        return Err(String::from("no servers given"));
+    }
+    parsed.options = time_limit(timeout, parsed.protocols);
+    Ok(Some(parsed))
+}
+
+// `--timeout` bounds every phase of a ping. The whole of it gets one timeout per protocol, so that
+// a server that stalls on one protocol is reported as such and the next one is still tried.
+fn time_limit(limit: Duration, protocols: &[PingProtocol]) -> PingOptions {
+    PingOptions::new()
+        .connect_timeout(limit)
+        .write_timeout(limit)
+        .read_timeout(limit)
+        .deadline(limit * protocols.len() as u32)
+}
+
+// Accepts `500ms`, `3s` or a plain number of seconds such as `1.5`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{}'", value);
+    let (number, scale) = match value.strip_suffix("ms") {
+        Some(millis) => (millis, 0.001),
+        None => (value.strip_suffix('s').unwrap_or(value), 1.0)
+    };
+    let number = number.parse::<f64>().map_err(|_| invalid())?;
+    Duration::try_from_secs_f64(number * scale).map_err(|_| invalid())
+}
+
+// Color only when writing text to a terminal, and never when NO_COLOR is set.
+fn color_depth(json: bool) -> Option<ColorDepth> {
+    if json || !io::stdout().is_terminal() || env::var_os("NO_COLOR").is_some() {
+        return None;
+    }
+    match env::var("COLORTERM") {
+        Ok(term) if term == "truecolor" || term == "24bit" => Some(ColorDepth::TrueColor),
+        _ => Some(ColorDepth::Ansi256)
+    }
+}
+
+// Pings every target at once, so a slow server only holds up its own line.
+fn ping_all(args: &Args) -> Vec<PingResult> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = args.targets.iter()
+            .map(|target| scope.spawn(move || {
+                let resolved = target.resolve(&SystemResolver::default())?;
+                ping_resolved(&resolved, args.protocols, args.options)
+            }))
+            .collect();
+        handles.into_iter().map(|handle| handle.join().expect("ping thread panicked")).collect()
+    })
+}
+
+fn print_text(target: &ServerAddress, result: &PingResult, depth: Option<ColorDepth>) {
+    println!("{}", target);
+    let (outcome, protocol) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            println!("  error     {}", describe(e));
+            return;
+        }
+    };
+    let status = &outcome.status;
+
+    match &status.version {
+        Some(version) => println!("  version   {} (protocol {})", version.server, version.protocol),
+        None => println!("  version   unknown")
+    }
+    println!("  protocol  {}", protocol);
+    let motd = match depth {
+        Some(depth) => status.motd.to_ansi(depth),
+        None => status.motd.to_plain()
+    };
+    println!("  motd      {}", motd.replace('\n', "\n            "));
+
+    let names: Vec<&str> = status.players.players().map(|sample| sample.name.as_str()).collect();
+    if names.is_empty() {
+        println!("  players   {}/{}", status.players.online, status.players.max);
+    } else {
+        println!("  players   {}/{} ({})", status.players.online, status.players.max, names.join(", "));
+    }
+    if let Some(latency) = outcome.latency {
+        println!("  latency   {:.1} ms", latency.as_secs_f64() * 1000.0);
+    }
+}
+
+// The error followed by its causes, since `PingError` leaves the I/O details to its source.
+fn describe(error: &PingError) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+// The status in the crate's JSON schema, next to what the ping itself found out.
+fn to_json(target: &ServerAddress, result: &PingResult) -> Value {
+    let (outcome, protocol) = match result {
+        Ok(result) => result,
+        Err(e) => return json!({ "address": target.to_string(), "error": describe(e) })
+    };
+
+    json!({
+        "address": target.to_string(),
+        "protocol": protocol,
+        "latency_ms": outcome.latency.map(|latency| latency.as_secs_f64() * 1000.0),
+        "status": outcome.status
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Args {
+        parse_args(args.iter().map(|arg| arg.to_string())).unwrap().unwrap()
+    }
+
+    #[test]
+    fn timeout_per_protocol() {
+        let args = parse(&["--timeout", "2s", "localhost"]);
+        assert_eq!(args.options.read_timeout, Duration::from_secs(2));
+        assert_eq!(args.options.deadline, Some(Duration::from_secs(8)));
+
+        let args = parse(&["localhost", "--timeout", "500ms", "--protocol", "modern"]);
+        assert_eq!(args.options.connect_timeout, Duration::from_millis(500));
+        assert_eq!(args.options.deadline, Some(Duration::from_millis(500)));
+
+        assert_eq!(parse(&["localhost"]).options.deadline, Some(Duration::from_secs(12)));
+    }
+}
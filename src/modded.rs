@@ -0,0 +1,318 @@
+use std::io::{Cursor, Error as IoError, ErrorKind as IoErrorKind};
+use byteorder::{ReadBytesExt, BE};
+use serde_json::Value;
+use crate::PingRead;
+
+// Version Forge reports for mods that only need to be present on the server.
+pub const SERVER_ONLY_VERSION: &str = "IGNORESERVERONLY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ModLoader {
+    // 1.7 to 1.12: `modinfo` with a plain mod list.
+    Fml1,
+    // 1.13 to 1.17: `forgeData` with mod and channel lists.
+    Fml2,
+    // 1.18+: `forgeData` with both lists packed into the binary `d` string.
+    Fml3
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModdedInfo {
+    pub loader: ModLoader,
+    pub mods: Vec<Mod>,
+    pub channels: Vec<Channel>,
+    // Forge drops entries when the list would make the status response too large.
+    pub truncated: bool
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mod {
+    pub id: String,
+    pub version: String
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Channel {
+    pub name: String,
+    pub version: String,
+    pub required: bool
+}
+
+impl ModdedInfo {
+    // Reads `forgeData` or `modinfo` from a status response object, if either is present and sane.
+    pub(crate) fn from_json(status: &Value) -> Option<ModdedInfo> {
+        if let Some(forge) = status.get("forgeData") {
+            return from_forge_data(forge);
+        }
+        let modinfo = status.get("modinfo")?;
+        Some(ModdedInfo {
+            loader: ModLoader::Fml1,
+            mods: modinfo.get("modList")?
+                .as_array()?
+                .iter()
+                .filter_map(|entry| Some(Mod { id: string(entry, "modid")?, version: string(entry, "version")? }))
+                .collect(),
+            channels: Vec::new(),
+            truncated: false
+        })
+    }
+}
+
+fn from_forge_data(forge: &Value) -> Option<ModdedInfo> {
+    let network_version = forge.get("fmlNetworkVersion").and_then(Value::as_i64).unwrap_or(2);
+
+    if let Some(encoded) = forge.get("d").and_then(Value::as_str) {
+        return decode_optimized(encoded).ok();
+    }
+
+    let list = |key: &str| forge.get(key).and_then(Value::as_array).cloned().unwrap_or_default();
+    Some(ModdedInfo {
+        loader: if network_version >= 3 { ModLoader::Fml3 } else { ModLoader::Fml2 },
+        mods: list("mods").iter()
+            .filter_map(|entry| Some(Mod { id: string(entry, "modId")?, version: string(entry, "modmarker")? }))
+            .collect(),
+        channels: list("channels").iter()
+            .filter_map(|entry| Some(Channel {
+                name: string(entry, "res")?,
+                version: string(entry, "version")?,
+                required: entry.get("required").and_then(Value::as_bool).unwrap_or(false)
+            }))
+            .collect(),
+        truncated: forge.get("truncated").and_then(Value::as_bool).unwrap_or(false)
+    })
+}
+
+fn string(entry: &Value, key: &str) -> Option<String> {
+    entry.get(key).and_then(Value::as_str).map(String::from)
+}
+
+// FML3 packs a byte buffer into a string 15 bits per char, after two chars holding the byte length,
+// so that JSON escaping does not blow up its size.
+fn decode_optimized(encoded: &str) -> Result<ModdedInfo, IoError> {
+    let chars: Vec<u32> = encoded.encode_utf16().map(|c| c as u32 & 0x7FFF).collect();
+    if chars.len() < 2 {
+        return Err(IoError::new(IoErrorKind::UnexpectedEof, "Truncated forgeData"));
+    }
+    let size = (chars[0] | (chars[1] << 15)) as usize;
+    if size * 8 > (chars.len() - 2) * 15 {
+        return Err(IoError::new(IoErrorKind::InvalidData, "forgeData length exceeds its payload"));
+    }
+
+    let mut bytes = Vec::with_capacity(size);
+    let mut buffer = 0u32;
+    let mut bits = 0u32;
+    for c in &chars[2..] {
+        while bits >= 8 {
+            bytes.push(buffer as u8);
+            buffer >>= 8;
+            bits -= 8;
+        }
+        buffer |= c << bits;
+        bits += 15;
+    }
+    while bytes.len() < size {
+        bytes.push(buffer as u8);
+        buffer >>= 8;
+    }
+    bytes.truncate(size);
+
+    let mut reader = Cursor::new(bytes);
+    let truncated = reader.read_u8()? != 0;
+
+    let mut mods = Vec::new();
+    let mut channels = Vec::new();
+    for _ in 0..reader.read_u16::<BE>()? {
+        let flags = reader.read_var_i32()?;
+        let id = reader.read_utf8_string()?;
+        let version = if flags & 0x1 != 0 {
+            String::from(SERVER_ONLY_VERSION)
+        } else {
+            reader.read_utf8_string()?
+        };
+        for _ in 0..flags >> 1 {
+            channels.push(Channel {
+                name: format!("{}:{}", id, reader.read_utf8_string()?),
+                version: reader.read_utf8_string()?,
+                required: reader.read_u8()? != 0
+            });
+        }
+        mods.push(Mod { id, version });
+    }
+    for _ in 0..reader.read_var_i32()? {
+        channels.push(Channel {
+            name: reader.read_utf8_string()?,
+            version: reader.read_utf8_string()?,
+            required: reader.read_u8()? != 0
+        });
+    }
+
+    Ok(ModdedInfo { loader: ModLoader::Fml3, mods, channels, truncated })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use serde_json::json;
+    use crate::PingWrite;
+
+    // The `d` string Forge 47.2.0 would send for the mods below, packed by its own encoder.
+    const FORGE_DATA: &str = "\u{49}\u{0}\u{0}\u{404}\u{1814}\u{137b}\u{5677}\u{cc}\u{dcd}\u{1917}\u{302e}\u{6818}\u{15a5}\u{7b93}\u{7735}\u{e4d}\u{1a5d}\u{33b7}\u{3103}\u{605c}\u{404}\u{1850}\u{2657}\u{2ece}\u{5c99}\u{3737}\u{796c}\u{2402}\u{25b4}\u{2b73}\u{2636}\u{4c2e}\u{1d19}\u{391d}\u{6765}\u{66d2}\u{15d1}\u{2393}\u{5460}\u{6989}\u{c}";
+
+    fn encode_optimized(bytes: &[u8]) -> String {
+        let mut chars = vec![bytes.len() as u16 & 0x7FFF, (bytes.len() >> 15) as u16 & 0x7FFF];
+        let mut buffer = 0u32;
+        let mut bits = 0u32;
+        for &byte in bytes {
+            if bits >= 15 {
+                chars.push(buffer as u16 & 0x7FFF);
+                buffer >>= 15;
+                bits -= 15;
+            }
+            buffer |= (byte as u32) << bits;
+            bits += 8;
+        }
+        if bits > 0 {
+            chars.push(buffer as u16 & 0x7FFF);
+        }
+        String::from_utf16(&chars).unwrap()
+    }
+
+    fn expected(truncated: bool) -> ModdedInfo {
+        ModdedInfo {
+            loader: ModLoader::Fml3,
+            mods: vec![
+                Mod { id: "forge".into(), version: "47.2.0".into() },
+                Mod { id: "serveronly".into(), version: SERVER_ONLY_VERSION.into() }
+            ],
+            channels: vec![
+                Channel { name: "forge:tier_sorting".into(), version: "1.0".into(), required: true },
+                Channel { name: "minecraft:register".into(), version: "FML3".into(), required: false }
+            ],
+            truncated
+        }
+    }
+
+    fn payload(truncated: bool) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        buffer.write_u8(truncated as u8).unwrap();
+        buffer.write_u16::<BE>(2).unwrap();
+        // One channel, sent with its version.
+        buffer.write_var_i32(1 << 1).unwrap();
+        buffer.write_utf8_string("forge").unwrap();
+        buffer.write_utf8_string("47.2.0").unwrap();
+        buffer.write_utf8_string("tier_sorting").unwrap();
+        buffer.write_utf8_string("1.0").unwrap();
+        buffer.write_u8(1).unwrap();
+        // Server-only, so no version follows.
+        buffer.write_var_i32(0x1).unwrap();
+        buffer.write_utf8_string("serveronly").unwrap();
+        buffer.write_var_i32(1).unwrap();
+        buffer.write_utf8_string("minecraft:register").unwrap();
+        buffer.write_utf8_string("FML3").unwrap();
+        buffer.write_u8(0).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn decode_fixed() {
+        assert_eq!(decode_optimized(FORGE_DATA).unwrap(), expected(false));
+        assert_eq!(encode_optimized(&payload(false)), FORGE_DATA);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        assert_eq!(decode_optimized(&encode_optimized(&payload(true))).unwrap(), expected(true));
+    }
+
+    #[test]
+    fn length_exceeds_payload() {
+        let mut chars: Vec<u16> = FORGE_DATA.encode_utf16().collect();
+        chars[0] += 1;
+        let error = decode_optimized(&String::from_utf16(&chars).unwrap()).unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::InvalidData);
+
+        assert_eq!(decode_optimized("\u{1}").unwrap_err().kind(), IoErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn payload_ends_early() {
+        let mut bytes = payload(false);
+        bytes.truncate(20);
+        assert_eq!(decode_optimized(&encode_optimized(&bytes)).unwrap_err().kind(), IoErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn forge_data_string() {
+        let status = json!({ "forgeData": { "d": FORGE_DATA, "fmlNetworkVersion": 3 } });
+        assert_eq!(ModdedInfo::from_json(&status), Some(expected(false)));
+
+        let status = json!({ "forgeData": { "d": "\u{5}\u{0}\u{1}" } });
+        assert_eq!(ModdedInfo::from_json(&status), None);
+    }
+
+    #[test]
+    fn forge_data_lists() {
+        let mut status = json!({
+            "forgeData": {
+                "channels": [
+                    { "res": "fml:handshake", "version": "1.2.3.4", "required": true },
+                    { "res": "forge:tier_sorting", "version": "1.0" },
+                    { "version": "1.0" }
+                ],
+                "mods": [
+                    { "modId": "forge", "modmarker": "ANY" },
+                    { "modId": "jei" }
+                ],
+                "fmlNetworkVersion": 2
+            }
+        });
+        let modded = ModdedInfo::from_json(&status).unwrap();
+        assert_eq!(modded, ModdedInfo {
+            loader: ModLoader::Fml2,
+            mods: vec![Mod { id: "forge".into(), version: "ANY".into() }],
+            channels: vec![
+                Channel { name: "fml:handshake".into(), version: "1.2.3.4".into(), required: true },
+                Channel { name: "forge:tier_sorting".into(), version: "1.0".into(), required: false }
+            ],
+            truncated: false
+        });
+
+        status["forgeData"]["fmlNetworkVersion"] = json!(3);
+        status["forgeData"]["truncated"] = json!(true);
+        let modded = ModdedInfo::from_json(&status).unwrap();
+        assert_eq!(modded.loader, ModLoader::Fml3);
+        assert!(modded.truncated);
+    }
+
+    #[test]
+    fn modinfo() {
+        let status = json!({
+            "modinfo": {
+                "type": "FML",
+                "modList": [
+                    { "modid": "mcp", "version": "9.42" },
+                    { "modid": "FML", "version": "8.0.99.99" },
+                    { "modid": "broken" }
+                ]
+            }
+        });
+        assert_eq!(ModdedInfo::from_json(&status), Some(ModdedInfo {
+            loader: ModLoader::Fml1,
+            mods: vec![
+                Mod { id: "mcp".into(), version: "9.42".into() },
+                Mod { id: "FML".into(), version: "8.0.99.99".into() }
+            ],
+            channels: Vec::new(),
+            truncated: false
+        }));
+
+        assert_eq!(ModdedInfo::from_json(&json!({ "modinfo": { "type": "FML" } })), None);
+        assert_eq!(ModdedInfo::from_json(&json!({ "version": { "name": "1.20.1" } })), None);
+    }
+}
@@ -0,0 +1,350 @@
+// Async counterparts of the blocking API, on top of tokio. Timeouts follow the same `PingOptions`
+// as the blocking functions; dropping a future closes its connection.
+
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task;
+use tokio::time;
+use crate::options::{timed_out, Timer};
+use crate::{
+    Outcome, PingError, PingOptions, PingProtocol, PingWrite, ResolvedAddress, ServerAddress, Session, Status,
+    SystemResolver, FALLBACK_ORDER, MAX_PACKET_LENGTH
+};
+
+pub async fn get_status<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<Status, PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect(address, &timer).await?;
+    Ok(drive(&mut stream, Session::any_legacy(), &timer).await?.status)
+}
+
+pub async fn get_status_with<O: Into<PingOptions>>(address: &SocketAddr, protocol: PingProtocol, options: O) -> Result<Status, PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect(address, &timer).await?;
+    let session = Session::new(protocol, &address.ip().to_string(), address.port())?;
+    Ok(drive(&mut stream, session, &timer).await?.status)
+}
+
+pub async fn ping_auto<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<(Status, PingProtocol), PingError> {
+    let timer = Timer::start(options.into());
+    let (outcome, protocol) = ping_first(&[*address], &address.ip().to_string(), address.port(), &FALLBACK_ORDER, false, &timer).await?;
+    Ok((outcome.status, protocol))
+}
+
+pub async fn ping_resolved<O: Into<PingOptions>>(address: &ResolvedAddress, protocols: &[PingProtocol], options: O) -> Result<(Outcome, PingProtocol), PingError> {
+    let timer = Timer::start(options.into());
+    ping_first(&address.addresses, &address.hostname, address.port, protocols, true, &timer).await
+}
+
+async fn ping_first(addresses: &[SocketAddr], hostname: &str, port: u16, protocols: &[PingProtocol], measure_latency: bool, timer: &Timer) -> Result<(Outcome, PingProtocol), PingError> {
+    let mut last_error = None;
+
+    for &protocol in protocols {
+        let mut stream = connect_any(addresses, timer).await?;
+        match drive(&mut stream, Session::new(protocol, hostname, port)?.measure_latency(measure_latency), timer).await {
+            Ok(outcome) => return Ok((outcome, protocol)),
+            Err(e) if e.is_protocol_mismatch() => last_error = Some(e),
+            Err(e) => return Err(e)
+        }
+    }
+
+    Err(last_error.unwrap_or_else(crate::no_protocols))
+}
+
+// Resolution goes through the blocking resolver on tokio's blocking thread pool.
+pub async fn get_status_host<O: Into<PingOptions>>(address: &ServerAddress, protocol: PingProtocol, options: O) -> Result<Status, PingError> {
+    let address = address.clone();
+    let resolved = task::spawn_blocking(move || address.resolve(&SystemResolver::default()))
+        .await
+        .map_err(IoError::other)??;
+    get_status_resolved(&resolved, protocol, options).await
+}
+
+pub async fn get_status_resolved<O: Into<PingOptions>>(address: &ResolvedAddress, protocol: PingProtocol, options: O) -> Result<Status, PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect_any(&address.addresses, &timer).await?;
+    Ok(drive(&mut stream, Session::new(protocol, &address.hostname, address.port)?, &timer).await?.status)
+}
+
+pub async fn get_status_legacy16<O: Into<PingOptions>>(address: &SocketAddr, hostname: &str, port: u16, options: O) -> Result<Status, PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect(address, &timer).await?;
+    Ok(drive(&mut stream, Session::new(PingProtocol::Legacy16, hostname, port)?, &timer).await?.status)
+}
+
+pub async fn get_status_modern<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<Status, PingError> {
+    get_status_with(address, PingProtocol::Modern, options).await
+}
+
+pub async fn get_status_and_latency<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<(Status, Duration), PingError> {
+    let timer = Timer::start(options.into());
+    let mut stream = connect(address, &timer).await?;
+    let session = Session::new(PingProtocol::Modern, &address.ip().to_string(), address.port())?
+        .measure_latency(true);
+    let outcome = drive(&mut stream, session, &timer).await?;
+    Ok((outcome.status, outcome.latency.unwrap_or_default()))
+}
+
+async fn connect(address: &SocketAddr, timer: &Timer) -> Result<TcpStream, PingError> {
+    let (timeout, phase) = timer.connect()?;
+    match time::timeout(timeout, TcpStream::connect(address)).await {
+        Ok(stream) => stream.map_err(|e| timed_out(e, phase)),
+        Err(elapsed) => Err(PingError::Timeout(phase, elapsed.into()))
+    }
+}
+
+async fn connect_any(addresses: &[SocketAddr], timer: &Timer) -> Result<TcpStream, PingError> {
+    let mut last_error = None;
+    for address in addresses {
+        match connect(address, timer).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e)
+        }
+    }
+    Err(last_error.unwrap_or_else(crate::no_addresses))
+}
+
+async fn drive(stream: &mut TcpStream, mut session: Session, timer: &Timer) -> Result<Outcome, PingError> {
+    match exchange(stream, &mut session, timer).await {
+        Ok(outcome) => Ok(outcome),
+        Err(e) => session.handle_close().ok_or(e)
+    }
+}
+
+async fn exchange(stream: &mut TcpStream, session: &mut Session, timer: &Timer) -> Result<Outcome, PingError> {
+    let mut buffer = [0u8; 4096];
+    loop {
+        while let Some(data) = session.poll_transmit(Instant::now()) {
+            let (timeout, phase) = timer.write()?;
+            match time::timeout(timeout, stream.write_all(&data)).await {
+                Ok(written) => written?,
+                Err(elapsed) => return Err(PingError::Timeout(phase, elapsed.into()))
+            }
+        }
+        let (timeout, phase) = timer.read()?;
+        let len = match time::timeout(timeout, stream.read(&mut buffer)).await {
+            Ok(len) => len?,
+            Err(elapsed) => return Err(PingError::Timeout(phase, elapsed.into()))
+        };
+        if len == 0 {
+            return Err(crate::connection_closed());
+        }
+        if let Some(outcome) = session.handle_input(&buffer[..len], Instant::now())? {
+            return Ok(outcome);
+        }
+    }
+}
+
+pub trait AsyncPingRead: AsyncRead + Unpin + Send {
+    fn read_var_i32(&mut self) -> impl Future<Output = Result<i32, IoError>> + Send {
+        async move {
+            let mut x = 0i32;
+
+            for shift in [0u32, 7, 14, 21, 28].iter() {
+                let b = self.read_u8().await? as i32;
+                x |= (b & 0x7F) << *shift;
+                if (b & 0x80) == 0 {
+                    return Ok(x);
+                }
+            }
+
+            Err(IoError::new(IoErrorKind::InvalidData, PingError::InvalidVarInt))
+        }
+    }
+
+    fn read_var_i64(&mut self) -> impl Future<Output = Result<i64, IoError>> + Send {
+        async move {
+            let mut x = 0i64;
+
+            for shift in [0u32, 7, 14, 21, 28, 35, 42, 49, 56, 63].iter() {
+                let b = self.read_u8().await? as i64;
+                x |= (b & 0x7F) << *shift;
+                if (b & 0x80) == 0 {
+                    return Ok(x);
+                }
+            }
+
+            Err(IoError::new(IoErrorKind::InvalidData, PingError::InvalidVarInt))
+        }
+    }
+
+    fn read_utf16_string(&mut self) -> impl Future<Output = Result<String, IoError>> + Send {
+        async move {
+            let len = self.read_u16().await?;
+            let mut chars = Vec::<u16>::new();
+            for _ in 0..len {
+                chars.push(self.read_u16().await?);
+            }
+            Ok(String::from_utf16_lossy(&chars))
+        }
+    }
+
+    fn read_utf8_string(&mut self) -> impl Future<Output = Result<String, IoError>> + Send {
+        async move {
+            let len = self.read_var_i32().await?;
+            if !(0..=MAX_PACKET_LENGTH).contains(&len) {
+                return Err(IoError::new(IoErrorKind::InvalidData, "String length out of range"));
+            }
+            let mut bytes = vec![0u8; len as usize];
+            self.read_exact(&mut bytes).await?;
+            String::from_utf8(bytes).map_err(|e| IoError::new(IoErrorKind::InvalidData, e))
+        }
+    }
+
+    fn read_packet(&mut self) -> impl Future<Output = Result<Vec<u8>, IoError>> + Send {
+        async move {
+            let len = self.read_var_i32().await?;
+            if !(0..=MAX_PACKET_LENGTH).contains(&len) {
+                return Err(IoError::new(IoErrorKind::InvalidData, "Packet length out of range"));
+            }
+            let mut packet = vec![0u8; len as usize];
+            self.read_exact(&mut packet).await?;
+            Ok(packet)
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send> AsyncPingRead for R {}
+
+// Values are encoded with the blocking `PingWrite` into a buffer and written in one go.
+pub trait AsyncPingWrite: AsyncWrite + Unpin + Send {
+    fn write_var_i32(&mut self, value: i32) -> impl Future<Output = Result<(), IoError>> + Send {
+        async move {
+            let mut buffer = Vec::new();
+            PingWrite::write_var_i32(&mut buffer, value)?;
+            self.write_all(&buffer).await
+        }
+    }
+
+    fn write_var_i64(&mut self, value: i64) -> impl Future<Output = Result<(), IoError>> + Send {
+        async move {
+            let mut buffer = Vec::new();
+            PingWrite::write_var_i64(&mut buffer, value)?;
+            self.write_all(&buffer).await
+        }
+    }
+
+    fn write_utf16_string<S>(&mut self, value: S) -> impl Future<Output = Result<(), IoError>> + Send where S: AsRef<str> + Send {
+        async move {
+            let mut buffer = Vec::new();
+            PingWrite::write_utf16_string(&mut buffer, value)?;
+            self.write_all(&buffer).await
+        }
+    }
+
+    fn write_utf8_string<S>(&mut self, value: S) -> impl Future<Output = Result<(), IoError>> + Send where S: AsRef<str> + Send {
+        async move {
+            let mut buffer = Vec::new();
+            PingWrite::write_utf8_string(&mut buffer, value)?;
+            self.write_all(&buffer).await
+        }
+    }
+
+    fn write_packet(&mut self, packet: &[u8]) -> impl Future<Output = Result<(), IoError>> + Send {
+        async move {
+            let mut buffer = Vec::new();
+            PingWrite::write_packet(&mut buffer, packet)?;
+            self.write_all(&buffer).await
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> AsyncPingWrite for W {}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+    use super::*;
+    use crate::{ChatComponent, PingRead, Players, StatusResponder, TimeoutPhase, Version};
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn codec_matches_blocking() {
+        let mut sync = Vec::new();
+        PingWrite::write_var_i32(&mut sync, -1).unwrap();
+        PingWrite::write_var_i64(&mut sync, i64::MIN).unwrap();
+        PingWrite::write_utf16_string(&mut sync, "\u{00a7}1").unwrap();
+        PingWrite::write_utf8_string(&mut sync, "h\u{e9}llo").unwrap();
+        PingWrite::write_packet(&mut sync, &[1, 2, 3]).unwrap();
+
+        let mut written = Vec::new();
+        block_on(async {
+            AsyncPingWrite::write_var_i32(&mut written, -1).await.unwrap();
+            AsyncPingWrite::write_var_i64(&mut written, i64::MIN).await.unwrap();
+            AsyncPingWrite::write_utf16_string(&mut written, "\u{00a7}1").await.unwrap();
+            AsyncPingWrite::write_utf8_string(&mut written, "h\u{e9}llo").await.unwrap();
+            AsyncPingWrite::write_packet(&mut written, &[1, 2, 3]).await.unwrap();
+        });
+        assert_eq!(written, sync);
+
+        let mut reader = &sync[..];
+        block_on(async {
+            assert_eq!(AsyncPingRead::read_var_i32(&mut reader).await.unwrap(), -1);
+            assert_eq!(AsyncPingRead::read_var_i64(&mut reader).await.unwrap(), i64::MIN);
+            assert_eq!(AsyncPingRead::read_utf16_string(&mut reader).await.unwrap(), "\u{00a7}1");
+            assert_eq!(AsyncPingRead::read_utf8_string(&mut reader).await.unwrap(), "h\u{e9}llo");
+            assert_eq!(AsyncPingRead::read_packet(&mut reader).await.unwrap(), [1, 2, 3]);
+        });
+        assert!(reader.is_empty());
+
+        let mut reader = &sync[..];
+        assert_eq!(PingRead::read_var_i32(&mut reader).unwrap(), -1);
+    }
+
+    #[test]
+    fn invalid_var_int() {
+        let mut reader = &[0xFFu8; 6][..];
+        let error = block_on(AsyncPingRead::read_var_i32(&mut reader)).unwrap_err();
+        assert!(matches!(PingError::from(error), PingError::InvalidVarInt));
+    }
+
+    #[test]
+    fn against_responder() {
+        let status = Status {
+            dirty: false,
+            version: Some(Version { protocol: 765, server: String::from("1.20.4") }),
+            motd: ChatComponent::from_legacy("\u{00a7}aAsync"),
+            favicon: None,
+            players: Players::new(3, 10),
+            modded: None
+        };
+        let responder = Arc::new(StatusResponder::bind("127.0.0.1:0", status.clone()).unwrap());
+        let address = responder.local_addr().unwrap();
+        let serving = Arc::clone(&responder);
+        thread::spawn(move || serving.serve());
+
+        block_on(async {
+            assert_eq!(get_status_with(&address, PingProtocol::Modern, TIMEOUT).await.unwrap(), status);
+            let legacy = get_status_with(&address, PingProtocol::Legacy16, TIMEOUT).await.unwrap();
+            assert_eq!(legacy.players, status.players);
+            let (found, protocol) = ping_auto(&address, TIMEOUT).await.unwrap();
+            assert_eq!((found, protocol), (status, PingProtocol::Modern));
+        });
+    }
+
+    #[test]
+    fn silent_server() {
+        // The connection sits in the backlog, so the request goes out but nothing ever answers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let options = PingOptions::from(TIMEOUT).read_timeout(Duration::from_millis(200));
+
+        let start = Instant::now();
+        let error = block_on(get_status_with(&address, PingProtocol::Modern, options)).unwrap_err();
+        assert!(matches!(error, PingError::Timeout(TimeoutPhase::Read, _)), "{:?}", error);
+        assert!(start.elapsed() < TIMEOUT);
+
+        let error = block_on(get_status_with(&address, PingProtocol::Modern, options.deadline(Duration::from_millis(100)))).unwrap_err();
+        assert!(matches!(error, PingError::Timeout(TimeoutPhase::Deadline, _)), "{:?}", error);
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::time::{Duration, Instant};
+use crate::PingError;
+
+// Timeouts for one ping. A bare `Duration` converts into options with that connect timeout, so the
+// functions that take options also accept the plain timeout they used to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PingOptions {
+    // Limit for establishing the connection, per resolved address.
+    pub connect_timeout: Duration,
+    // Limit for each write of a request.
+    pub write_timeout: Duration,
+    // Limit for each wait on the server while its response comes in.
+    pub read_timeout: Duration,
+    // Limit for the whole ping, connecting and every fallback attempt included. It starts once the
+    // address is resolved: name resolution is bounded by the resolver, e.g. `SystemResolver::timeout`.
+    pub deadline: Option<Duration>
+}
+
+// The part of a ping that ran out of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeoutPhase {
+    Connect,
+    Write,
+    Read,
+    // The overall deadline passed, whichever phase was running.
+    Deadline
+}
+
+impl PingOptions {
+    pub fn new() -> PingOptions {
+        PingOptions::default()
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> PingOptions {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> PingOptions {
+        self.write_timeout = timeout;
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> PingOptions {
+        self.read_timeout = timeout;
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Duration) -> PingOptions {
+        self.deadline = Some(deadline);
+        self
+    }
+}
+
+impl Default for PingOptions {
+    fn default() -> Self {
+        PingOptions {
+            connect_timeout: Duration::from_secs(3),
+            write_timeout: Duration::from_secs(3),
+            read_timeout: Duration::from_secs(3),
+            deadline: None
+        }
+    }
+}
+
+impl From<Duration> for PingOptions {
+    fn from(connect_timeout: Duration) -> Self {
+        PingOptions { connect_timeout, ..PingOptions::default() }
+    }
+}
+
+impl fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TimeoutPhase::Connect => "Timed out connecting",
+            TimeoutPhase::Write => "Timed out sending the request",
+            TimeoutPhase::Read => "Timed out waiting for the response",
+            TimeoutPhase::Deadline => "Deadline exceeded"
+        })
+    }
+}
+
+// The clock of one ping: hands out the timeout for each phase, cut short by the overall deadline.
+// Each limit comes with the phase to blame should it expire.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timer {
+    options: PingOptions,
+    deadline: Option<Instant>
+}
+
+impl Timer {
+    pub(crate) fn start(options: PingOptions) -> Timer {
+        Timer { options, deadline: options.deadline.map(|deadline| Instant::now() + deadline) }
+    }
+
+    pub(crate) fn connect(&self) -> Result<(Duration, TimeoutPhase), PingError> {
+        self.limit(self.options.connect_timeout, TimeoutPhase::Connect)
+    }
+
+    pub(crate) fn write(&self) -> Result<(Duration, TimeoutPhase), PingError> {
+        self.limit(self.options.write_timeout, TimeoutPhase::Write)
+    }
+
+    pub(crate) fn read(&self) -> Result<(Duration, TimeoutPhase), PingError> {
+        self.limit(self.options.read_timeout, TimeoutPhase::Read)
+    }
+
+    fn limit(&self, timeout: Duration, phase: TimeoutPhase) -> Result<(Duration, TimeoutPhase), PingError> {
+        let remaining = match self.deadline {
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+            None => return Ok((timeout, phase))
+        };
+        if remaining.is_zero() {
+            Err(PingError::Timeout(TimeoutPhase::Deadline, IoError::from(IoErrorKind::TimedOut)))
+        } else if remaining < timeout {
+            Ok((remaining, TimeoutPhase::Deadline))
+        } else {
+            Ok((timeout, phase))
+        }
+    }
+}
+
+// Blocking sockets report an expired timeout as `WouldBlock` or `TimedOut` depending on the platform.
+pub(crate) fn timed_out(error: IoError, phase: TimeoutPhase) -> PingError {
+    match error.kind() {
+        IoErrorKind::WouldBlock | IoErrorKind::TimedOut => PingError::Timeout(phase, error),
+        _ => error.into()
+    }
+}
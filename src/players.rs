@@ -0,0 +1,184 @@
+use std::fmt;
+use std::str::FromStr;
+use serde_json::Value;
+use crate::{ChatComponent, PingError};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Players {
+    pub online: i32,
+    pub max: i32,
+    pub sample: Vec<PlayerSample>
+}
+
+// One entry of `players.sample`. Besides real players, servers use entries with the all-zero
+// UUID to put arbitrary (often `§`-coded) lines into the client's player-count tooltip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlayerSample {
+    pub name: String,
+    pub id: Uuid
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uuid(pub u128);
+
+impl Players {
+    pub fn new(online: i32, max: i32) -> Players {
+        Players { online, max, sample: Vec::new() }
+    }
+
+    // Sample entries that look like actual players rather than tooltip text.
+    pub fn players(&self) -> impl Iterator<Item = &PlayerSample> {
+        self.sample.iter().filter(|sample| !sample.is_placeholder())
+    }
+
+    // The tooltip the vanilla client shows: every sample name as a line, with names that
+    // themselves contain line breaks split up.
+    pub fn hover_text(&self) -> Vec<ChatComponent> {
+        self.sample.iter()
+            .flat_map(|sample| sample.name.split('\n'))
+            .map(ChatComponent::from_legacy)
+            .collect()
+    }
+
+    pub(crate) fn from_json(players: &Value) -> Option<Players> {
+        let count = |name: &str| players.get(name)
+            .and_then(Value::as_i64)
+            .and_then(|count| i32::try_from(count).ok());
+
+        // Entries without a usable id are still shown by the client, so keep them as placeholders.
+        let sample = match players.get("sample") {
+            Some(Value::Array(sample)) => sample.iter()
+                .filter_map(|entry| Some(PlayerSample {
+                    name: entry.get("name")?.as_str()?.to_owned(),
+                    id: entry.get("id")
+                        .and_then(Value::as_str)
+                        .and_then(|id| id.parse::<Uuid>().ok())
+                        .unwrap_or_default()
+                }))
+                .collect(),
+            _ => Vec::new()
+        };
+
+        Some(Players { online: count("online")?, max: count("max")?, sample })
+    }
+}
+
+impl PlayerSample {
+    pub fn is_placeholder(&self) -> bool {
+        self.id.is_nil() || self.name.contains('\u{00a7}') || self.name.contains('\n')
+    }
+
+    pub fn name_component(&self) -> ChatComponent {
+        ChatComponent::from_legacy(&self.name)
+    }
+}
+
+impl Uuid {
+    pub const NIL: Uuid = Uuid(0);
+
+    pub fn is_nil(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = PingError;
+
+    // Accepts both the hyphenated and the bare 32-digit form.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex: String = s.chars().filter(|c| *c != '-').collect();
+        if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(PingError::malformed("id", s));
+        }
+        u128::from_str_radix(&hex, 16).map(Uuid).map_err(|_| PingError::malformed("id", s))
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+        write!(f, "{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    const NOTCH: Uuid = Uuid(0x069a79f444e94726a5befca90e38aaf5);
+
+    fn sample(name: &str, id: Uuid) -> PlayerSample {
+        PlayerSample { name: String::from(name), id }
+    }
+
+    #[test]
+    fn uuid() {
+        assert_eq!("069a79f4-44e9-4726-a5be-fca90e38aaf5".parse::<Uuid>().unwrap(), NOTCH);
+        assert_eq!("069A79F444E94726A5BEFCA90E38AAF5".parse::<Uuid>().unwrap(), NOTCH);
+        assert_eq!(NOTCH.to_string(), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+        assert_eq!(Uuid::NIL.to_string(), "00000000-0000-0000-0000-000000000000");
+        assert!(Uuid::NIL.is_nil());
+        assert!(!NOTCH.is_nil());
+    }
+
+    #[test]
+    fn invalid_uuid() {
+        for invalid in ["", "069a79f4", "069a79f4-44e9-4726-a5be-fca90e38aaf", "069a79f4-44e9-4726-a5be-fca90e38aaf5a", "+69a79f444e94726a5befca90e38aaf5", "g69a79f444e94726a5befca90e38aaf5"] {
+            assert!(matches!(invalid.parse::<Uuid>(), Err(PingError::MalformedResponse { field: "id", .. })), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn from_json() {
+        let players = Players::from_json(&json!({
+            "online": 3,
+            "max": 20,
+            "sample": [
+                { "name": "Notch", "id": "069a79f4-44e9-4726-a5be-fca90e38aaf5" },
+                { "name": "No id" },
+                { "name": "Bad id", "id": "not-a-uuid" },
+                { "id": "069a79f4-44e9-4726-a5be-fca90e38aaf5" },
+                "not an object"
+            ]
+        })).unwrap();
+        assert_eq!(players.online, 3);
+        assert_eq!(players.max, 20);
+        assert_eq!(players.sample, vec![sample("Notch", NOTCH), sample("No id", Uuid::NIL), sample("Bad id", Uuid::NIL)]);
+        assert_eq!(players.players().collect::<Vec<_>>(), vec![&sample("Notch", NOTCH)]);
+    }
+
+    #[test]
+    fn from_json_counts() {
+        assert_eq!(Players::from_json(&json!({ "online": 0, "max": 0 })), Some(Players::new(0, 0)));
+        assert_eq!(Players::from_json(&json!({ "online": 1, "max": 2, "sample": "none" })), Some(Players::new(1, 2)));
+        assert_eq!(Players::from_json(&json!({ "online": -1, "max": 2 })), Some(Players::new(-1, 2)));
+        assert_eq!(Players::from_json(&json!({ "max": 20 })), None);
+        assert_eq!(Players::from_json(&json!({ "online": "3", "max": 20 })), None);
+        assert_eq!(Players::from_json(&json!({ "online": 3, "max": 4_294_967_296i64 })), None);
+        assert_eq!(Players::from_json(&json!([])), None);
+    }
+
+    #[test]
+    fn placeholders() {
+        assert!(!sample("Notch", NOTCH).is_placeholder());
+        assert!(sample("Notch", Uuid::NIL).is_placeholder());
+        assert!(sample("\u{00a7}aWelcome", NOTCH).is_placeholder());
+        assert!(sample("Line\nbreak", NOTCH).is_placeholder());
+    }
+
+    #[test]
+    fn hover_text() {
+        let mut players = Players::new(1, 10);
+        players.sample = vec![
+            sample("\u{00a7}6Welcome!\n\u{00a7}7Line two", Uuid::NIL),
+            sample("Notch", NOTCH)
+        ];
+        let lines: Vec<String> = players.hover_text().iter().map(ChatComponent::to_plain).collect();
+        assert_eq!(lines, vec!["Welcome!", "Line two", "Notch"]);
+        assert_eq!(players.hover_text()[0], ChatComponent::from_legacy("\u{00a7}6Welcome!"));
+        assert_eq!(sample("\u{00a7}bName", NOTCH).name_component(), ChatComponent::from_legacy("\u{00a7}bName"));
+    }
+}
@@ -0,0 +1,417 @@
+use std::io::{BufRead, Cursor, Error as IoError, ErrorKind as IoErrorKind, Read};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{SystemTime, UNIX_EPOCH};
+use byteorder::{ReadBytesExt, WriteBytesExt, BE, LE};
+use crate::options::{timed_out, Timer};
+use crate::{ChatComponent, PingError, PingOptions, Players};
+
+const QUERY_MAGIC: [u8; 2] = [0xFE, 0xFD];
+const TYPE_HANDSHAKE: u8 = 0x09;
+const TYPE_STAT: u8 = 0x00;
+// Servers only use the low four bits of each byte of the session id.
+const SESSION_ID_MASK: i32 = 0x0F0F0F0F;
+// Fixed filler the server puts before the key/value section and before the player list.
+const FULL_STAT_PADDING: usize = 11;
+const PLAYER_LIST_PADDING: usize = 10;
+
+// Answer to a basic stat request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BasicStat {
+    pub motd: ChatComponent,
+    pub game_type: String,
+    pub map: String,
+    pub players: Players,
+    pub host_port: u16,
+    pub host_ip: String
+}
+
+// Answer to a full stat request: everything in `BasicStat` plus software, plugins and the
+// names of everyone online.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FullStat {
+    pub motd: ChatComponent,
+    pub game_type: String,
+    pub game_id: String,
+    pub version: String,
+    // Server software as given before the plugin list, e.g. `Paper on Bukkit 1.20.4`. Empty on vanilla.
+    pub software: String,
+    pub plugins: Vec<Plugin>,
+    pub map: String,
+    pub players: Players,
+    pub player_names: Vec<String>,
+    pub host_port: u16,
+    pub host_ip: String
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Plugin {
+    pub name: String,
+    // Empty when the server lists the plugin without a version.
+    pub version: String
+}
+
+// Fetches a basic stat from the Query port (`query.port` in server.properties, by default the
+// game port). Only the read timeout and the deadline of `options` apply.
+pub fn query_basic<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<BasicStat, PingError> {
+    let timer = Timer::start(options.into());
+    let client = QueryClient::handshake(address, &timer)?;
+    let response = client.stat(&timer, false)?;
+    parse_basic_stat(&response)
+}
+
+pub fn query_full<O: Into<PingOptions>>(address: &SocketAddr, options: O) -> Result<FullStat, PingError> {
+    let timer = Timer::start(options.into());
+    let client = QueryClient::handshake(address, &timer)?;
+    let response = client.stat(&timer, true)?;
+    parse_full_stat(&response)
+}
+
+// A socket that has completed the handshake and holds the challenge token the server expects
+// with every stat request.
+struct QueryClient {
+    socket: UdpSocket,
+    session_id: i32,
+    token: i32
+}
+
+impl QueryClient {
+    fn handshake(address: &SocketAddr, timer: &Timer) -> Result<QueryClient, PingError> {
+        let socket = crate::udp_connect(address)?;
+
+        let session_id = session_id();
+        let response = exchange(&socket, timer, TYPE_HANDSHAKE, session_id, &[])?;
+        // The token comes as a decimal string, but goes back as a 32-bit integer.
+        let token = read_string(&mut Cursor::new(&response[..]))?;
+        let token = token.parse::<i32>().map_err(|_| PingError::malformed("challenge token", &token))?;
+
+        Ok(QueryClient { socket, session_id, token })
+    }
+
+    // Requests a stat, returning the payload after the type and session id.
+    fn stat(&self, timer: &Timer, full: bool) -> Result<Vec<u8>, PingError> {
+        let mut payload = Vec::with_capacity(8);
+        payload.write_i32::<BE>(self.token)?;
+        if full {
+            // Padding is what tells the server to answer with a full stat.
+            payload.extend_from_slice(&[0; 4]);
+        }
+        exchange(&self.socket, timer, TYPE_STAT, self.session_id, &payload)
+    }
+}
+
+fn session_id() -> i32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.subsec_nanos() as i32 ^ since.as_secs() as i32)
+        .unwrap_or_default();
+    now & SESSION_ID_MASK
+}
+
+// Sends one request and waits for the response of the same type and session. Anything else that
+// arrives, such as a late answer to an earlier request, is skipped.
+fn exchange(socket: &UdpSocket, timer: &Timer, packet_type: u8, session_id: i32, payload: &[u8]) -> Result<Vec<u8>, PingError> {
+    let mut request = Vec::with_capacity(7 + payload.len());
+    request.extend_from_slice(&QUERY_MAGIC);
+    request.write_u8(packet_type)?;
+    request.write_i32::<BE>(session_id)?;
+    request.extend_from_slice(payload);
+    socket.send(&request)?;
+
+    let mut buffer = [0u8; 65535];
+    loop {
+        let (timeout, phase) = timer.read()?;
+        socket.set_read_timeout(Some(timeout))?;
+        let len = socket.recv(&mut buffer).map_err(|e| timed_out(e, phase))?;
+        let mut response = Cursor::new(&buffer[..len]);
+        let response_type = response.read_u8()?;
+        if response.read_i32::<BE>()? != session_id {
+            continue;
+        }
+        if response_type != packet_type {
+            return Err(PingError::UnexpectedPacketId(response_type as i32));
+        }
+        return Ok(buffer[5..len].to_vec());
+    }
+}
+
+fn parse_basic_stat(response: &[u8]) -> Result<BasicStat, PingError> {
+    let mut reader = Cursor::new(response);
+    let motd = read_string(&mut reader)?;
+    let game_type = read_string(&mut reader)?;
+    let map = read_string(&mut reader)?;
+    let online = read_string(&mut reader)?;
+    let max = read_string(&mut reader)?;
+    // The one little-endian field of the protocol.
+    let host_port = reader.read_u16::<LE>()?;
+    let host_ip = read_string(&mut reader)?;
+
+    Ok(BasicStat {
+        motd: ChatComponent::from_legacy(&motd),
+        game_type,
+        map,
+        players: Players::new(crate::parse_field(&online, "numplayers", &online)?, crate::parse_field(&max, "maxplayers", &max)?),
+        host_port,
+        host_ip
+    })
+}
+
+fn parse_full_stat(response: &[u8]) -> Result<FullStat, PingError> {
+    let mut reader = Cursor::new(response);
+    skip(&mut reader, FULL_STAT_PADDING)?;
+
+    let mut fields = Vec::new();
+    loop {
+        let key = read_string(&mut reader)?;
+        if key.is_empty() {
+            break;
+        }
+        fields.push((key, read_string(&mut reader)?));
+    }
+    let field = |key: &str| fields.iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.clone())
+        .unwrap_or_default();
+
+    skip(&mut reader, PLAYER_LIST_PADDING)?;
+    let mut player_names = Vec::new();
+    loop {
+        let name = read_string(&mut reader)?;
+        if name.is_empty() {
+            break;
+        }
+        player_names.push(name);
+    }
+
+    let (online, max, host_port) = (field("numplayers"), field("maxplayers"), field("hostport"));
+    let (software, plugins) = parse_plugins(&field("plugins"));
+    Ok(FullStat {
+        motd: ChatComponent::from_legacy(&field("hostname")),
+        game_type: field("gametype"),
+        game_id: field("game_id"),
+        version: field("version"),
+        software,
+        plugins,
+        map: field("map"),
+        players: Players::new(
+            crate::parse_field(&online, "numplayers", &online)?,
+            crate::parse_field(&max, "maxplayers", &max)?
+        ),
+        player_names,
+        host_port: crate::parse_field(&host_port, "hostport", &host_port)?,
+        host_ip: field("hostip")
+    })
+}
+
+// Bukkit-based servers report `software: Name version; Other version`, vanilla an empty string.
+fn parse_plugins(plugins: &str) -> (String, Vec<Plugin>) {
+    let (software, list) = match plugins.split_once(": ") {
+        Some((software, list)) => (software, list),
+        None => (plugins, "")
+    };
+    let plugins = list.split("; ")
+        .filter(|plugin| !plugin.is_empty())
+        .map(|plugin| match plugin.rsplit_once(' ') {
+            Some((name, version)) => Plugin { name: String::from(name), version: String::from(version) },
+            None => Plugin { name: String::from(plugin), version: String::new() }
+        })
+        .collect();
+    (String::from(software), plugins)
+}
+
+fn read_string(reader: &mut Cursor<&[u8]>) -> Result<String, PingError> {
+    let mut bytes = Vec::new();
+    reader.read_until(0, &mut bytes)?;
+    if bytes.pop() != Some(0) {
+        return Err(IoError::new(IoErrorKind::UnexpectedEof, "Unterminated string in query response").into());
+    }
+    // Vanilla writes UTF-8, older servers and plugins Latin-1.
+    Ok(String::from_utf8(bytes).unwrap_or_else(|e| e.into_bytes().iter().map(|b| *b as char).collect()))
+}
+
+fn skip(reader: &mut Cursor<&[u8]>, len: usize) -> Result<(), PingError> {
+    let mut padding = vec![0u8; len];
+    reader.read_exact(&mut padding)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use super::*;
+
+    const TOKEN: i32 = 9513307;
+
+    fn strings(values: &[&[u8]]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.iter().copied().chain([0])).collect()
+    }
+
+    fn basic_stat() -> Vec<u8> {
+        let mut response = strings(&[b"A \xa7aMinecraft Server", b"SMP", b"world", b"2", b"20"]);
+        response.write_u16::<LE>(25565).unwrap();
+        response.extend(strings(&[b"127.0.0.1"]));
+        response
+    }
+
+    fn full_stat() -> Vec<u8> {
+        let mut response = b"splitnum\0\x80\0".to_vec();
+        response.extend(strings(&[
+            b"hostname", b"Caf\xe9 server",
+            b"gametype", b"SMP",
+            b"game_id", b"MINECRAFT",
+            b"version", b"1.20.4",
+            b"plugins", b"Paper on Bukkit 1.20.4: WorldEdit 7.2.15; Essentials",
+            b"map", b"world",
+            b"numplayers", b"2",
+            b"maxplayers", b"20",
+            b"hostport", b"25565",
+            b"hostip", b"127.0.0.1",
+            b""
+        ]));
+        response.extend_from_slice(b"\x01player_\0\0");
+        response.extend(strings(&[b"alice", b"bob", b""]));
+        response
+    }
+
+    fn eof<T>(result: Result<T, PingError>) -> bool {
+        matches!(result, Err(PingError::Io(e)) if e.kind() == IoErrorKind::UnexpectedEof)
+    }
+
+    // Answers a handshake and then one stat request, each preceded by a reply to a stale session.
+    fn fake_server() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 2048];
+            let reply = |packet_type: u8, session_id: i32, payload: &[u8], client: SocketAddr| {
+                let mut response = vec![packet_type];
+                response.write_i32::<BE>(session_id).unwrap();
+                response.extend_from_slice(payload);
+                socket.send_to(&response, client).unwrap();
+            };
+
+            let (len, client) = socket.recv_from(&mut buffer).unwrap();
+            assert_eq!(len, 7);
+            assert_eq!(buffer[..3], [0xFE, 0xFD, TYPE_HANDSHAKE]);
+            let session_id = i32::from_be_bytes(buffer[3..7].try_into().unwrap());
+            assert_eq!(session_id & !SESSION_ID_MASK, 0);
+            reply(TYPE_HANDSHAKE, session_id ^ 1, b"1\0", client);
+            reply(TYPE_HANDSHAKE, session_id, &strings(&[TOKEN.to_string().as_bytes()]), client);
+
+            let (len, client) = socket.recv_from(&mut buffer).unwrap();
+            assert_eq!(buffer[..3], [0xFE, 0xFD, TYPE_STAT]);
+            assert_eq!(i32::from_be_bytes(buffer[3..7].try_into().unwrap()), session_id);
+            assert_eq!(i32::from_be_bytes(buffer[7..11].try_into().unwrap()), TOKEN);
+            let response = match len {
+                11 => basic_stat(),
+                15 => full_stat(),
+                len => panic!("unexpected stat request length {}", len)
+            };
+            reply(TYPE_STAT, session_id ^ 1, b"stale\0", client);
+            reply(TYPE_STAT, session_id, &response, client);
+        });
+        address
+    }
+
+    #[test]
+    fn basic() {
+        let stat = query_basic(&fake_server(), Duration::from_secs(2)).unwrap();
+        assert_eq!(stat, BasicStat {
+            motd: ChatComponent::from_legacy("A \u{00a7}aMinecraft Server"),
+            game_type: String::from("SMP"),
+            map: String::from("world"),
+            players: Players::new(2, 20),
+            host_port: 25565,
+            host_ip: String::from("127.0.0.1")
+        });
+    }
+
+    #[test]
+    fn full() {
+        let stat = query_full(&fake_server(), Duration::from_secs(2)).unwrap();
+        assert_eq!(stat, FullStat {
+            motd: ChatComponent::from_legacy("Caf\u{e9} server"),
+            game_type: String::from("SMP"),
+            game_id: String::from("MINECRAFT"),
+            version: String::from("1.20.4"),
+            software: String::from("Paper on Bukkit 1.20.4"),
+            plugins: vec![
+                Plugin { name: String::from("WorldEdit"), version: String::from("7.2.15") },
+                Plugin { name: String::from("Essentials"), version: String::new() }
+            ],
+            map: String::from("world"),
+            players: Players::new(2, 20),
+            player_names: vec![String::from("alice"), String::from("bob")],
+            host_port: 25565,
+            host_ip: String::from("127.0.0.1")
+        });
+    }
+
+    #[test]
+    fn silent_server() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let options = PingOptions::new().read_timeout(Duration::from_millis(100));
+        let result = query_basic(&socket.local_addr().unwrap(), options);
+        assert!(matches!(result, Err(PingError::Timeout(crate::TimeoutPhase::Read, _))));
+    }
+
+    #[test]
+    fn plugins() {
+        assert_eq!(parse_plugins(""), (String::new(), Vec::new()));
+        assert_eq!(parse_plugins("CraftBukkit on Bukkit 1.20.4"), (String::from("CraftBukkit on Bukkit 1.20.4"), Vec::new()));
+        assert_eq!(parse_plugins("Paper on Bukkit 1.20.4: "), (String::from("Paper on Bukkit 1.20.4"), Vec::new()));
+        assert_eq!(
+            parse_plugins("Paper: Dynmap 3.7 beta 4; LuckPerms 5.4.102"),
+            (String::from("Paper"), vec![
+                Plugin { name: String::from("Dynmap 3.7 beta"), version: String::from("4") },
+                Plugin { name: String::from("LuckPerms"), version: String::from("5.4.102") }
+            ])
+        );
+    }
+
+    #[test]
+    fn latin1_fallback() {
+        let mut reader = Cursor::new(&b"Caf\xc3\xa9\0Caf\xe9\0"[..]);
+        assert_eq!(read_string(&mut reader).unwrap(), "Caf\u{e9}");
+        assert_eq!(read_string(&mut reader).unwrap(), "Caf\u{e9}");
+    }
+
+    #[test]
+    fn unterminated() {
+        let mut reader = Cursor::new(&b"no terminator"[..]);
+        assert!(eof(read_string(&mut reader)));
+
+        let mut response = basic_stat();
+        response.pop();
+        assert!(eof(parse_basic_stat(&response)));
+        let mut response = full_stat();
+        response.pop();
+        assert!(eof(parse_full_stat(&response)));
+    }
+
+    #[test]
+    fn truncated() {
+        // Cut inside the little-endian port.
+        let response = basic_stat();
+        let port = response.len() - b"127.0.0.1\0".len() - 2;
+        assert!(eof(parse_basic_stat(&response[..port + 1])));
+
+        // Cut inside either padding, and right after the player list's.
+        let response = full_stat();
+        let players = response.len() - b"\x01player_\0\0alice\0bob\0\0".len();
+        assert!(eof(parse_full_stat(&response[..5])));
+        assert!(eof(parse_full_stat(&response[..players + 4])));
+        assert!(eof(parse_full_stat(&response[..players + 10])));
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        let mut response = strings(&[b"motd", b"SMP", b"world", b"many", b"20"]);
+        response.write_u16::<LE>(25565).unwrap();
+        response.extend(strings(&[b"127.0.0.1"]));
+        assert!(matches!(parse_basic_stat(&response), Err(PingError::MalformedResponse { field: "numplayers", .. })));
+    }
+}
@@ -0,0 +1,229 @@
+use std::io::{Cursor, Error as IoError, ErrorKind as IoErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use crate::options::{timed_out, Timer};
+use crate::{PingError, PingOptions};
+
+pub const RCON_DEFAULT_PORT: u16 = 25575;
+
+const TYPE_RESPONSE: i32 = 0;
+const TYPE_COMMAND: i32 = 2;
+const TYPE_AUTH_RESPONSE: i32 = 2;
+const TYPE_LOGIN: i32 = 3;
+// Servers answer the login with this request id when the password is wrong.
+const AUTH_FAILED_ID: i32 = -1;
+// Vanilla refuses packets with a longer body.
+const MAX_COMMAND_LENGTH: usize = 1446;
+// Vanilla splits output into bodies of at most 4096 characters, each up to three bytes of UTF-8.
+const MAX_RESPONSE_LENGTH: i32 = 4096 * 3 + 10;
+
+// An authenticated RCON connection. Each call gets its own timeouts from the options given to
+// `connect`, so a long-lived client is not cut off by the deadline.
+pub struct Rcon {
+    stream: TcpStream,
+    options: PingOptions,
+    next_id: i32
+}
+
+impl Rcon {
+    pub fn connect<O: Into<PingOptions>>(address: &SocketAddr, password: &str, options: O) -> Result<Rcon, PingError> {
+        let options = options.into();
+        let timer = Timer::start(options);
+        let (timeout, phase) = timer.connect()?;
+        let stream = TcpStream::connect_timeout(address, timeout).map_err(|e| timed_out(e, phase))?;
+
+        let mut rcon = Rcon { stream, options, next_id: 1 };
+        let id = rcon.send(&timer, TYPE_LOGIN, password)?;
+        // Some servers send an empty response ahead of the actual answer.
+        loop {
+            let (response_id, packet_type, _) = rcon.receive(&timer)?;
+            if packet_type != TYPE_AUTH_RESPONSE {
+                continue;
+            }
+            return match response_id {
+                AUTH_FAILED_ID => Err(PingError::AuthenticationFailed),
+                response_id if response_id == id => Ok(rcon),
+                response_id => Err(PingError::UnexpectedPacketId(response_id))
+            };
+        }
+    }
+
+    // Runs a command and returns its output. Long output arrives split over several packets, so
+    // an empty packet follows the command: the server answers in order, and its reply marks the
+    // end of the output.
+    pub fn command(&mut self, command: &str) -> Result<String, PingError> {
+        if command.len() > MAX_COMMAND_LENGTH {
+            return Err(IoError::new(IoErrorKind::InvalidInput, "RCON command too long").into());
+        }
+        let timer = Timer::start(self.options);
+        let id = self.send(&timer, TYPE_COMMAND, command)?;
+        let end = self.send(&timer, TYPE_RESPONSE, "")?;
+
+        let mut output = Vec::new();
+        loop {
+            let (response_id, _, body) = self.receive(&timer)?;
+            match response_id {
+                response_id if response_id == id => output.extend_from_slice(&body),
+                response_id if response_id == end => break,
+                response_id => return Err(PingError::UnexpectedPacketId(response_id))
+            }
+        }
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    fn send(&mut self, timer: &Timer, packet_type: i32, body: &str) -> Result<i32, PingError> {
+        let id = self.next_id;
+        // Stay clear of -1, which stands for a failed login.
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+
+        let mut packet = Vec::with_capacity(14 + body.len());
+        packet.write_i32::<LE>(10 + body.len() as i32)?;
+        packet.write_i32::<LE>(id)?;
+        packet.write_i32::<LE>(packet_type)?;
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+
+        let (timeout, phase) = timer.write()?;
+        self.stream.set_write_timeout(Some(timeout))?;
+        self.stream.write_all(&packet).map_err(|e| timed_out(e, phase))?;
+        Ok(id)
+    }
+
+    // Reads one packet: request id, type and body without its terminating nulls.
+    fn receive(&mut self, timer: &Timer) -> Result<(i32, i32, Vec<u8>), PingError> {
+        let mut length = [0u8; 4];
+        self.read_exact(timer, &mut length)?;
+        let length = i32::from_le_bytes(length);
+        if !(10..=MAX_RESPONSE_LENGTH).contains(&length) {
+            return Err(IoError::new(IoErrorKind::InvalidData, "RCON packet length out of range").into());
+        }
+
+        let mut packet = vec![0u8; length as usize];
+        self.read_exact(timer, &mut packet)?;
+        let mut reader = Cursor::new(&packet[..]);
+        let id = reader.read_i32::<LE>()?;
+        let packet_type = reader.read_i32::<LE>()?;
+        let mut body = packet.split_off(8);
+        while body.last() == Some(&0) {
+            body.pop();
+        }
+        Ok((id, packet_type, body))
+    }
+
+    fn read_exact(&mut self, timer: &Timer, buffer: &mut [u8]) -> Result<(), PingError> {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let (timeout, phase) = timer.read()?;
+            self.stream.set_read_timeout(Some(timeout))?;
+            match self.stream.read(&mut buffer[filled..]).map_err(|e| timed_out(e, phase))? {
+                0 => return Err(crate::connection_closed()),
+                len => filled += len
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+    use super::*;
+
+    const PASSWORD: &str = "secret";
+
+    fn packet(id: i32, packet_type: i32, body: &str) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&(10 + body.len() as i32).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&packet_type.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet
+    }
+
+    fn read_packet(stream: &mut TcpStream) -> Option<(i32, i32, String)> {
+        let mut length = [0u8; 4];
+        stream.read_exact(&mut length).ok()?;
+        let mut packet = vec![0u8; i32::from_le_bytes(length) as usize];
+        stream.read_exact(&mut packet).ok()?;
+        let id = i32::from_le_bytes(packet[0..4].try_into().unwrap());
+        let packet_type = i32::from_le_bytes(packet[4..8].try_into().unwrap());
+        Some((id, packet_type, String::from_utf8(packet[8..packet.len() - 2].to_vec()).unwrap()))
+    }
+
+    // Answers like vanilla: output split every 4096 characters, unknown packet types answered
+    // with an error message. Every byte goes out in its own write to exercise reassembly.
+    fn fake_server(output: fn(&str) -> String) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                thread::spawn(move || {
+                    let (id, _, password) = read_packet(&mut stream).unwrap();
+                    stream.write_all(&packet(id, TYPE_RESPONSE, "")).unwrap();
+                    if password != PASSWORD {
+                        stream.write_all(&packet(AUTH_FAILED_ID, TYPE_AUTH_RESPONSE, "")).unwrap();
+                        return;
+                    }
+                    stream.write_all(&packet(id, TYPE_AUTH_RESPONSE, "")).unwrap();
+
+                    while let Some((id, packet_type, body)) = read_packet(&mut stream) {
+                        let mut reply = Vec::new();
+                        if packet_type == TYPE_COMMAND {
+                            let chars: Vec<char> = output(&body).chars().collect();
+                            for chunk in chars.chunks(4096) {
+                                reply.extend(packet(id, TYPE_RESPONSE, &chunk.iter().collect::<String>()));
+                            }
+                        } else {
+                            reply.extend(packet(id, TYPE_RESPONSE, &format!("Unknown request {:x}", packet_type)));
+                        }
+                        for byte in reply {
+                            stream.write_all(&[byte]).unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn login_and_command() {
+        let address = fake_server(|command| format!("ran {}", command));
+        let mut rcon = Rcon::connect(&address, PASSWORD, Duration::from_secs(2)).unwrap();
+        assert_eq!(rcon.command("list").unwrap(), "ran list");
+        assert_eq!(rcon.command("tps").unwrap(), "ran tps");
+    }
+
+    #[test]
+    fn wrong_password() {
+        let address = fake_server(|_| String::new());
+        let result = Rcon::connect(&address, "wrong", Duration::from_secs(2));
+        assert!(matches!(result, Err(PingError::AuthenticationFailed)));
+    }
+
+    #[test]
+    fn multi_packet_output() {
+        let address = fake_server(|_| "x".repeat(10000));
+        let mut rcon = Rcon::connect(&address, PASSWORD, Duration::from_secs(2)).unwrap();
+        assert_eq!(rcon.command("big").unwrap(), "x".repeat(10000));
+    }
+
+    #[test]
+    fn non_ascii_output() {
+        let address = fake_server(|_| "\u{00a7}".repeat(5000) + "\u{20ac}".repeat(4096).as_str());
+        let mut rcon = Rcon::connect(&address, PASSWORD, Duration::from_secs(2)).unwrap();
+        assert_eq!(rcon.command("list").unwrap(), "\u{00a7}".repeat(5000) + "\u{20ac}".repeat(4096).as_str());
+    }
+
+    #[test]
+    fn command_too_long() {
+        let address = fake_server(|_| String::new());
+        let mut rcon = Rcon::connect(&address, PASSWORD, Duration::from_secs(2)).unwrap();
+        assert!(rcon.command(&"x".repeat(MAX_COMMAND_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use std::io::{Cursor, ErrorKind as IoErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+use serde_json::{json, Map, Value};
+use crate::{ModLoader, PingError, PingProtocol, PingRead, PingWrite, Status};
+
+// How long a client may take to send its request before the connection is dropped.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+// How long to wait after a 0xFE for the bytes that tell the legacy eras apart.
+const LEGACY_WAIT: Duration = Duration::from_millis(100);
+// Most connections answered at once, each on its own thread. Connections beyond it are closed.
+const MAX_CONNECTIONS: usize = 256;
+// Pause after an accept error that is not down to a single client, such as running out of file
+// descriptors, so that the loop does not spin while it lasts.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+// Answers Server List Pings of every era with a fixed status, e.g. as a maintenance placeholder or
+// as a stand-in server in tests. Logins are not supported; such connections are closed.
+pub struct StatusResponder {
+    listener: TcpListener,
+    status: Arc<RwLock<Status>>,
+    connections: Arc<AtomicUsize>
+}
+
+// Holds one of the `MAX_CONNECTIONS` slots until the handler is done with it.
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl StatusResponder {
+    pub fn bind<A: ToSocketAddrs>(address: A, status: Status) -> Result<StatusResponder, PingError> {
+        Ok(StatusResponder {
+            listener: TcpListener::bind(address)?,
+            status: Arc::new(RwLock::new(status)),
+            connections: Arc::new(AtomicUsize::new(0))
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, PingError> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // Replaces the status for connections accepted from now on.
+    pub fn set_status(&self, status: Status) {
+        *self.status.write().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+
+    // Accepts connections until the listener turns out to be unusable, answering each on its own
+    // thread. A client that misbehaves only loses its own connection, and failing accepts are
+    // retried.
+    pub fn serve(&self) -> Result<(), PingError> {
+        loop {
+            let mut stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) => match e.kind() {
+                    IoErrorKind::ConnectionAborted | IoErrorKind::ConnectionReset | IoErrorKind::Interrupted => continue,
+                    IoErrorKind::InvalidInput => return Err(e.into()),
+                    _ => {
+                        thread::sleep(ACCEPT_BACKOFF);
+                        continue;
+                    }
+                }
+            };
+            // At the limit, dropping the stream closes the connection straight away.
+            let slot = match self.claim_slot() {
+                Some(slot) => slot,
+                None => continue
+            };
+            let status = self.status.read().unwrap_or_else(|e| e.into_inner()).clone();
+            thread::spawn(move || {
+                let _slot = slot;
+                let _ = respond(&mut stream, &status);
+            });
+        }
+    }
+
+    fn claim_slot(&self) -> Option<Slot> {
+        self.connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| (active < MAX_CONNECTIONS).then_some(active + 1))
+            .ok()
+            .map(|_| Slot(Arc::clone(&self.connections)))
+    }
+}
+
+// Answers the ping on a single accepted connection, whichever era the client speaks.
+pub fn respond(stream: &mut TcpStream, status: &Status) -> Result<(), PingError> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut first = [0u8; 1];
+    if stream.peek(&mut first)? == 0 {
+        return Ok(());
+    }
+    if first[0] == 0xFE {
+        respond_legacy(stream, status)?;
+    } else {
+        respond_modern(stream, status)?;
+    }
+    // Let the client read everything before the connection goes away.
+    stream.shutdown(Shutdown::Write)?;
+    Ok(())
+}
+
+// Beta clients send a bare 0xFE, 1.4 adds 0x01 and 1.6 follows up with an MC|PingHost plugin
+// message, which has to be read in full so that closing does not reset the connection.
+fn respond_legacy(stream: &mut TcpStream, status: &Status) -> Result<(), PingError> {
+    stream.read_u8()?;
+    stream.set_read_timeout(Some(LEGACY_WAIT))?;
+    let beta = !matches!(read_optional_u8(stream)?, Some(0x01));
+    if !beta && read_optional_u8(stream)? == Some(0xFA) {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.read_utf16_string()?; // MC|PingHost
+        let length = stream.read_u16::<BE>()?;
+        stream.take(length as u64).read_to_end(&mut Vec::new())?;
+    }
+
+    let era = if beta { PingProtocol::Beta } else { PingProtocol::Legacy16 };
+    stream.write_all(&status.encode_legacy(era)?)?;
+    Ok(())
+}
+
+fn read_optional_u8(stream: &mut TcpStream) -> Result<Option<u8>, PingError> {
+    match stream.read_u8() {
+        Ok(byte) => Ok(Some(byte)),
+        Err(e) if matches!(e.kind(), IoErrorKind::WouldBlock | IoErrorKind::TimedOut | IoErrorKind::UnexpectedEof) => Ok(None),
+        Err(e) => Err(e.into())
+    }
+}
+
+// Handshake, status request and Ping, each answered as vanilla does.
+fn respond_modern(stream: &mut TcpStream, status: &Status) -> Result<(), PingError> {
+    let handshake = stream.read_packet()?;
+    let mut handshake = Cursor::new(&handshake[..]);
+    let packet_id = handshake.read_var_i32()?;
+    if packet_id != 0x00 {
+        return Err(PingError::UnexpectedPacketId(packet_id));
+    }
+    handshake.read_var_i32()?; // protocol version
+    handshake.read_utf8_string()?; // hostname
+    handshake.read_u16::<BE>()?; // port
+    if handshake.read_var_i32()? != 1 {
+        return Ok(());
+    }
+
+    loop {
+        let packet = match stream.read_packet() {
+            Ok(packet) => packet,
+            // Clients that do not measure latency hang up after the status.
+            Err(e) if e.kind() == IoErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into())
+        };
+        let mut request = Cursor::new(&packet[..]);
+        let mut response = Vec::new();
+        match request.read_var_i32()? {
+            0x00 => {
+                response.write_var_i32(0x00)?;
+                response.write_utf8_string(status_json(status).to_string())?;
+                stream.write_packet(&response)?;
+            }
+            0x01 => {
+                response.write_var_i32(0x01)?;
+                response.write_i64::<BE>(request.read_i64::<BE>()?)?;
+                return Ok(stream.write_packet(&response)?);
+            }
+            packet_id => return Err(PingError::UnexpectedPacketId(packet_id))
+        }
+    }
+}
+
+fn status_json(status: &Status) -> Value {
+    let mut players = Map::new();
+    players.insert(String::from("max"), json!(status.players.max));
+    players.insert(String::from("online"), json!(status.players.online));
+    if !status.players.sample.is_empty() {
+        players.insert(String::from("sample"), status.players.sample.iter()
+            .map(|sample| json!({ "name": sample.name, "id": sample.id.to_string() }))
+            .collect());
+    }
+
+    let mut response = Map::new();
+    if let Some(version) = &status.version {
+        response.insert(String::from("version"), json!({ "name": version.server, "protocol": version.protocol }));
+    }
+    response.insert(String::from("players"), Value::Object(players));
+    response.insert(String::from("description"), status.motd.to_json());
+    if let Some(favicon) = &status.favicon {
+        response.insert(String::from("favicon"), Value::String(favicon.to_data_uri()));
+    }
+
+    if let Some(modded) = &status.modded {
+        let mods = |id: &str, version: &str| modded.mods.iter()
+            .map(|entry| json!({ id: entry.id, version: entry.version }))
+            .collect::<Vec<_>>();
+        match modded.loader {
+            ModLoader::Fml1 => {
+                response.insert(String::from("modinfo"), json!({ "type": "FML", "modList": mods("modid", "version") }));
+            }
+            // FML3 lists written out in full, rather than packed into `d`, still read as FML3.
+            ModLoader::Fml2 | ModLoader::Fml3 => {
+                let channels: Vec<Value> = modded.channels.iter()
+                    .map(|channel| json!({ "res": channel.name, "version": channel.version, "required": channel.required }))
+                    .collect();
+                response.insert(String::from("forgeData"), json!({
+                    "channels": channels,
+                    "mods": mods("modId", "modmarker"),
+                    "fmlNetworkVersion": if modded.loader == ModLoader::Fml3 { 3 } else { 2 },
+                    "truncated": modded.truncated
+                }));
+            }
+        }
+    }
+    Value::Object(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::{ChatComponent, PlayerSample, Players, Uuid, Version};
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn status() -> Status {
+        let mut players = Players::new(1, 20);
+        players.sample.push(PlayerSample { name: String::from("alice"), id: Uuid(0x069a79f444e94726a5befca90e38aaf5) });
+        Status {
+            dirty: false,
+            version: Some(Version { protocol: 765, server: String::from("1.20.4") }),
+            motd: ChatComponent::from_legacy("\u{00a7}6A test server"),
+            favicon: None,
+            players,
+            modded: None
+        }
+    }
+
+    fn start(status: Status) -> (SocketAddr, Arc<StatusResponder>) {
+        let responder = Arc::new(StatusResponder::bind("127.0.0.1:0", status).unwrap());
+        let address = responder.local_addr().unwrap();
+        let serving = Arc::clone(&responder);
+        thread::spawn(move || serving.serve());
+        (address, responder)
+    }
+
+    #[test]
+    fn every_protocol() {
+        let (address, _responder) = start(status());
+
+        let modern = crate::get_status_with(&address, PingProtocol::Modern, TIMEOUT).unwrap();
+        assert_eq!(modern, status());
+
+        for protocol in [PingProtocol::Legacy16, PingProtocol::Legacy14] {
+            let legacy = crate::get_status_with(&address, protocol, TIMEOUT).unwrap();
+            assert_eq!(legacy.version, status().version);
+            assert_eq!(legacy.motd, status().motd);
+            assert_eq!(legacy.players, Players::new(1, 20));
+        }
+
+        let beta = crate::get_status_with(&address, PingProtocol::Beta, TIMEOUT).unwrap();
+        assert_eq!(beta.version, None);
+        assert_eq!(beta.motd.to_plain(), "A test server");
+        assert_eq!(beta.players, Players::new(1, 20));
+
+        let any = crate::get_status(&address, TIMEOUT).unwrap();
+        assert_eq!(any.version, status().version);
+    }
+
+    #[test]
+    fn ping_auto() {
+        let (address, _responder) = start(status());
+        let (found, protocol) = crate::ping_auto(&address, TIMEOUT).unwrap();
+        assert_eq!(protocol, PingProtocol::Modern);
+        assert_eq!(found, status());
+    }
+
+    #[test]
+    fn latency() {
+        let (address, _responder) = start(status());
+        let (found, latency) = crate::get_status_and_latency(&address, TIMEOUT).unwrap();
+        assert_eq!(found, status());
+        assert!(latency < TIMEOUT);
+    }
+
+    #[test]
+    fn set_status() {
+        let (address, responder) = start(status());
+        responder.set_status(Status { players: Players::new(7, 8), ..status() });
+        let found = crate::get_status_with(&address, PingProtocol::Modern, TIMEOUT).unwrap();
+        assert_eq!(found.players, Players::new(7, 8));
+    }
+
+    #[test]
+    fn connection_limit() {
+        let (address, _responder) = start(status());
+        // Clients that connect and say nothing hold their slot until `CLIENT_TIMEOUT`.
+        let idle: Vec<_> = (0..MAX_CONNECTIONS).map(|_| TcpStream::connect(address).unwrap()).collect();
+        thread::sleep(Duration::from_millis(200));
+
+        let mut extra = TcpStream::connect(address).unwrap();
+        extra.set_read_timeout(Some(TIMEOUT)).unwrap();
+        assert_eq!(extra.read(&mut [0u8; 16]).unwrap(), 0);
+
+        drop(idle);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(crate::get_status_with(&address, PingProtocol::Modern, TIMEOUT).unwrap(), status());
+    }
+
+    #[test]
+    fn login_closed() {
+        let (address, _responder) = start(status());
+        let mut stream = TcpStream::connect(address).unwrap();
+        let mut handshake = Vec::new();
+        handshake.write_var_i32(0x00).unwrap();
+        handshake.write_var_i32(765).unwrap();
+        handshake.write_utf8_string("localhost").unwrap();
+        handshake.write_u16::<BE>(address.port()).unwrap();
+        handshake.write_var_i32(2).unwrap();
+        stream.write_packet(&handshake).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        assert_eq!(stream.read(&mut [0u8; 16]).unwrap(), 0);
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::{ping_many, BatchOptions, PingError, PingMany, PingOptions, PingProtocol, Status};
+
+// A block of addresses such as `10.0.0.0/16` or `2001:db8::/120`. A bare address is a block of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    // Number of connection attempts in flight at once.
+    pub concurrency: usize,
+    // Timeouts for each address and port.
+    pub ping: PingOptions,
+    // Upper bound on connection attempts started per second.
+    pub rate: Option<u32>,
+    // Protocol to speak, or `None` to fall back through all of them like `ping_auto`.
+    pub protocol: Option<PingProtocol>
+}
+
+// Servers found by `scan`, in the order they answer.
+pub struct Scan {
+    results: PingMany<SocketAddr>
+}
+
+impl Cidr {
+    pub fn new(address: IpAddr, prefix: u8) -> Result<Cidr, PingError> {
+        let bits = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128
+        };
+        if prefix > bits {
+            return Err(PingError::InvalidAddress(format!("{}/{}", address, prefix)));
+        }
+        // Normalise to the first address of the block.
+        let network = from_bits(address, to_bits(address) & !host_mask(bits, prefix));
+        Ok(Cidr { network, prefix })
+    }
+
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        address.is_ipv4() == self.network.is_ipv4()
+            && to_bits(*address) & !host_mask(self.bits(), self.prefix) == to_bits(self.network)
+    }
+
+    // Every address in the block, network and broadcast addresses included.
+    pub fn addresses(&self) -> impl Iterator<Item = IpAddr> + Send + 'static {
+        let network = self.network;
+        let first = to_bits(network);
+        let last = first | host_mask(self.bits(), self.prefix);
+        (first..=last).map(move |bits| from_bits(network, bits))
+    }
+
+    fn bits(&self) -> u8 {
+        match self.network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = PingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PingError::InvalidAddress(s.to_owned());
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None)
+        };
+        let address = address.parse::<IpAddr>().map_err(|_| invalid())?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None if address.is_ipv4() => 32,
+            None => 128
+        };
+        Cidr::new(address, prefix)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            concurrency: 256,
+            ping: PingOptions::from(Duration::from_secs(1)),
+            rate: None,
+            protocol: None
+        }
+    }
+}
+
+// Pings every port in `ports` on every address in `blocks`, yielding the ones that answer.
+// Targets are generated lazily, so large blocks cost no memory up front.
+pub fn scan(blocks: &[Cidr], ports: &[RangeInclusive<u16>], options: ScanOptions) -> Scan {
+    let blocks = blocks.to_vec();
+    let ports = ports.to_vec();
+    let targets = blocks.into_iter()
+        .flat_map(|block| block.addresses())
+        .flat_map(move |address| {
+            ports.clone().into_iter().flatten().map(move |port| SocketAddr::new(address, port))
+        });
+
+    let batch = BatchOptions {
+        concurrency: options.concurrency,
+        ping: options.ping,
+        deadline: None,
+        protocol: options.protocol
+    };
+    let results = match options.rate.filter(|rate| *rate > 0) {
+        Some(rate) => ping_many(Paced::new(targets, rate), batch),
+        None => ping_many(targets, batch)
+    };
+
+    Scan { results }
+}
+
+impl Iterator for Scan {
+    type Item = (SocketAddr, Status);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.results.by_ref().find_map(|(address, result)| result.ok().map(|status| (address, status)))
+    }
+}
+
+// Spaces out items so that at most `rate` are handed out per second. The batch workers pull
+// targets one at a time, so this paces connection attempts.
+struct Paced<I> {
+    inner: I,
+    interval: Duration,
+    next: Instant
+}
+
+impl<I> Paced<I> {
+    fn new(inner: I, rate: u32) -> Paced<I> {
+        Paced { inner, interval: Duration::from_secs(1) / rate, next: Instant::now() }
+    }
+}
+
+impl<I: Iterator> Iterator for Paced<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next()?;
+        let now = Instant::now();
+        if self.next > now {
+            thread::sleep(self.next - now);
+        }
+        self.next = self.next.max(now) + self.interval;
+        Some(item)
+    }
+}
+
+fn to_bits(address: IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(address) => u32::from(address) as u128,
+        IpAddr::V6(address) => u128::from(address)
+    }
+}
+
+fn from_bits(family: IpAddr, bits: u128) -> IpAddr {
+    match family {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(bits as u32)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(bits))
+    }
+}
+
+fn host_mask(bits: u8, prefix: u8) -> u128 {
+    let host_bits = (bits - prefix) as u32;
+    if host_bits == 128 {
+        u128::MAX
+    } else {
+        (1u128 << host_bits) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use super::*;
+    use crate::{ChatComponent, Players, StatusResponder};
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_normalise() {
+        assert_eq!(cidr("10.1.2.3/16").network(), ip("10.1.0.0"));
+        assert_eq!(cidr("10.1.2.3/16").prefix(), 16);
+        assert_eq!(cidr("10.1.2.3/16").to_string(), "10.1.0.0/16");
+        assert_eq!(cidr("10.1.2.3"), cidr("10.1.2.3/32"));
+        assert_eq!(cidr("10.1.2.3/32").network(), ip("10.1.2.3"));
+        assert_eq!(cidr("10.1.2.3/0").to_string(), "0.0.0.0/0");
+
+        assert_eq!(cidr("2001:db8:1:2::5/32").to_string(), "2001:db8::/32");
+        assert_eq!(cidr("2001:db8::5"), cidr("2001:db8::5/128"));
+        assert_eq!(cidr("2001:db8::5/0").to_string(), "::/0");
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for invalid in ["", "10.0.0.0/33", "::/129", "10.0.0.0/", "10.0.0.0/-1", "10.0.0/8", "host/8"] {
+            assert!(matches!(invalid.parse::<Cidr>(), Err(PingError::InvalidAddress(raw)) if raw == invalid), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn contains() {
+        assert!(cidr("10.1.0.0/16").contains(&ip("10.1.255.255")));
+        assert!(!cidr("10.1.0.0/16").contains(&ip("10.2.0.0")));
+        assert!(cidr("0.0.0.0/0").contains(&ip("255.255.255.255")));
+        assert!(!cidr("0.0.0.0/0").contains(&ip("::")));
+        assert!(cidr("::/0").contains(&ip("ffff::1")));
+        assert!(!cidr("::/0").contains(&ip("0.0.0.0")));
+    }
+
+    #[test]
+    fn addresses() {
+        let block: Vec<IpAddr> = cidr("192.0.2.9/30").addresses().collect();
+        assert_eq!(block, vec![ip("192.0.2.8"), ip("192.0.2.9"), ip("192.0.2.10"), ip("192.0.2.11")]);
+        assert_eq!(cidr("192.0.2.9/32").addresses().collect::<Vec<_>>(), vec![ip("192.0.2.9")]);
+
+        let top: Vec<IpAddr> = cidr("255.255.255.255/31").addresses().collect();
+        assert_eq!(top, vec![ip("255.255.255.254"), ip("255.255.255.255")]);
+
+        let block: Vec<IpAddr> = cidr("2001:db8::/127").addresses().collect();
+        assert_eq!(block, vec![ip("2001:db8::"), ip("2001:db8::1")]);
+
+        let everything: Vec<IpAddr> = cidr("::/0").addresses().take(2).collect();
+        assert_eq!(everything, vec![ip("::"), ip("::1")]);
+        let everything: Vec<IpAddr> = cidr("0.0.0.0/0").addresses().take(2).collect();
+        assert_eq!(everything, vec![ip("0.0.0.0"), ip("0.0.0.1")]);
+    }
+
+    #[test]
+    fn scan_finds_responder() {
+        let status = Status {
+            dirty: false,
+            version: None,
+            motd: ChatComponent::from_legacy("Found me"),
+            favicon: None,
+            players: Players::new(0, 10),
+            modded: None
+        };
+        // The responder and, on the ports right after it, listeners that never answer; retried
+        // until all of those ports are free.
+        let (responder, _silent) = loop {
+            let responder = StatusResponder::bind("127.0.0.1:0", status.clone()).unwrap();
+            let port = responder.local_addr().unwrap().port();
+            if port > u16::MAX - 3 {
+                continue;
+            }
+            let silent: Result<Vec<TcpListener>, _> = (1..=3)
+                .map(|offset| TcpListener::bind(("127.0.0.1", port + offset)))
+                .collect();
+            if let Ok(silent) = silent {
+                break (responder, silent);
+            }
+        };
+        let address = responder.local_addr().unwrap();
+        thread::spawn(move || responder.serve());
+
+        let options = ScanOptions {
+            ping: PingOptions::new().read_timeout(Duration::from_millis(200)).deadline(Duration::from_secs(1)),
+            ..ScanOptions::default()
+        };
+        let found: Vec<(SocketAddr, Status)> = scan(&[cidr("127.0.0.1/32")], &[address.port()..=address.port() + 3], options).collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, address);
+        assert_eq!(found[0].1.motd.to_plain(), "Found me");
+    }
+
+    #[test]
+    fn paced() {
+        let start = Instant::now();
+        assert_eq!(Paced::new(0..5, 100).count(), 5);
+        // The first item goes out at once, the other four 10 ms apart.
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,181 @@
+// Serde support for the types that do not derive it. The JSON form these produce is documented
+// with the crate, under "JSON schema".
+
+use serde::de::{Deserialize, Deserializer, Error as DeError};
+use serde::ser::{Serialize, Serializer};
+use serde_json::Value;
+use crate::{ChatComponent, Color, Favicon, Uuid};
+
+impl Serialize for ChatComponent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChatComponent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(ChatComponent::from_json(&Value::deserialize(deserializer)?))
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Color::from_name(&name).ok_or_else(|| D::Error::custom(format!("unknown color {:?}", name)))
+    }
+}
+
+impl Serialize for Favicon {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_data_uri())
+    }
+}
+
+impl<'de> Deserialize<'de> for Favicon {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Favicon::from_data_uri(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Uuid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Uuid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+    use serde::de::DeserializeOwned;
+    use super::*;
+    use crate::{
+        BedrockStatus, Channel, FullStat, Mod, ModLoader, ModdedInfo, PingProtocol, PlayerSample, Players, Plugin, Status,
+        Version
+    };
+
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) {
+        let json = serde_json::to_string(value).unwrap();
+        assert_eq!(&serde_json::from_str::<T>(&json).unwrap(), value, "{}", json);
+    }
+
+    fn favicon() -> Favicon {
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 13];
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&64u32.to_be_bytes());
+        png.extend_from_slice(&64u32.to_be_bytes());
+        Favicon::from_png(png).unwrap()
+    }
+
+    fn players() -> Players {
+        let mut players = Players::new(1, 20);
+        players.sample.push(PlayerSample { name: String::from("alice"), id: Uuid(0x069a79f444e94726a5befca90e38aaf5) });
+        players
+    }
+
+    fn status() -> Status {
+        Status {
+            dirty: false,
+            version: Some(Version { protocol: 765, server: String::from("1.20.4") }),
+            motd: ChatComponent::text("A Minecraft Server"),
+            favicon: None,
+            players: players(),
+            modded: None
+        }
+    }
+
+    #[test]
+    fn status_round_trip() {
+        round_trip(&status());
+        round_trip(&Status {
+            dirty: true,
+            version: None,
+            motd: ChatComponent::from_legacy("\u{00a7}6Gold \u{00a7}lbold\u{00a7}r and #plain"),
+            favicon: Some(favicon()),
+            players: players(),
+            modded: Some(ModdedInfo {
+                loader: ModLoader::Fml3,
+                mods: vec![Mod { id: String::from("forge"), version: String::from("47.2.0") }],
+                channels: vec![Channel { name: String::from("forge:tier_sorting"), version: String::from("1.0"), required: true }],
+                truncated: true
+            })
+        });
+        round_trip(&PingProtocol::Legacy16);
+    }
+
+    #[test]
+    fn bedrock_round_trip() {
+        round_trip(&BedrockStatus {
+            edition: String::from("MCPE"),
+            motd: ChatComponent::from_legacy("\u{00a7}bDedicated Server"),
+            version: Version { protocol: 649, server: String::from("1.20.62") },
+            players: Players::new(2, 10),
+            server_id: -4_657_210_582_017_392_413,
+            sub_motd: Some(ChatComponent::text("Bedrock level")),
+            game_mode: Some(String::from("Survival")),
+            port_v4: Some(19132),
+            port_v6: None
+        });
+    }
+
+    #[test]
+    fn full_stat_round_trip() {
+        round_trip(&FullStat {
+            motd: ChatComponent::text("A Minecraft Server"),
+            game_type: String::from("SMP"),
+            game_id: String::from("MINECRAFT"),
+            version: String::from("1.20.4"),
+            software: String::from("Paper on Bukkit 1.20.4"),
+            plugins: vec![
+                Plugin { name: String::from("WorldEdit"), version: String::from("7.2.15") },
+                Plugin { name: String::from("Essentials"), version: String::new() }
+            ],
+            map: String::from("world"),
+            players: Players::new(1, 20),
+            player_names: vec![String::from("alice")],
+            host_port: 25565,
+            host_ip: String::from("127.0.0.1")
+        });
+    }
+
+    // Pins the documented schema: a change here is a breaking change for stored results.
+    #[test]
+    fn status_schema() {
+        let status = Status {
+            motd: ChatComponent::from_legacy("\u{00a7}6Gold"),
+            modded: Some(ModdedInfo {
+                loader: ModLoader::Fml2,
+                mods: vec![Mod { id: String::from("forge"), version: String::from("ANY") }],
+                channels: vec![Channel { name: String::from("fml:handshake"), version: String::from("1.2.3.4"), required: true }],
+                truncated: false
+            }),
+            ..status()
+        };
+        assert_eq!(serde_json::to_string(&status).unwrap(), concat!(
+            r#"{"dirty":false,"version":{"protocol":765,"server":"1.20.4"},"#,
+            r#""motd":{"extra":[{"color":"gold","text":"Gold"}],"text":""},"favicon":null,"#,
+            r#""players":{"online":1,"max":20,"sample":[{"name":"alice","id":"069a79f4-44e9-4726-a5be-fca90e38aaf5"}]},"#,
+            r#""modded":{"loader":"fml2","mods":[{"id":"forge","version":"ANY"}],"#,
+            r#""channels":[{"name":"fml:handshake","version":"1.2.3.4","required":true}],"truncated":false}}"#
+        ));
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(serde_json::from_str::<Uuid>("\"not-a-uuid\"").is_err());
+        assert!(serde_json::from_str::<Favicon>("\"data:image/png;base64,AAAA\"").is_err());
+        assert!(serde_json::from_str::<Color>("\"#+fffff\"").is_err());
+        assert_eq!(serde_json::from_str::<Color>("\"#ff8800\"").unwrap().name(), "#ff8800");
+    }
+}
@@ -0,0 +1,625 @@
+use std::io::{Cursor, Error as IoError, ErrorKind as IoErrorKind, Write};
+use std::time::{Duration, Instant};
+use byteorder::{WriteBytesExt, ReadBytesExt, BE};
+use serde_json::Value;
+use crate::{
+    ChatComponent, Favicon, ModdedInfo, PingError, PingProtocol, PingRead, PingWrite, Players, Status, Version,
+    LEGACY_PROTOCOL_VERSION, MAX_PACKET_LENGTH, PROTOCOL_VERSION
+};
+
+// The client side of one ping, without any I/O. Hand whatever `poll_transmit` returns to the
+// server and everything received to `handle_input` until it yields an `Outcome`.
+#[derive(Debug)]
+pub struct Session {
+    protocol: PingProtocol,
+    answer: Answer,
+    measure_latency: bool,
+    outgoing: Vec<u8>,
+    incoming: Vec<u8>,
+    state: State
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub status: Status,
+    // Round trip of the modern Ping/Pong exchange, when requested.
+    pub latency: Option<Duration>
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Answer {
+    Json,
+    Legacy,
+    // Either kick format: servers that may predate 1.4, and newer ones answering a beta ping.
+    AnyKick
+}
+
+#[derive(Debug)]
+enum State {
+    AwaitingStatus,
+    AwaitingPong {
+        status: Box<Status>,
+        payload: i64,
+        sent: Option<Instant>
+    },
+    Finished
+}
+
+impl Session {
+    pub fn new(protocol: PingProtocol, hostname: &str, port: u16) -> Result<Session, PingError> {
+        let answer = match protocol {
+            PingProtocol::Beta => Answer::AnyKick,
+            PingProtocol::Legacy14 | PingProtocol::Legacy16 => Answer::Legacy,
+            PingProtocol::Modern => Answer::Json
+        };
+        Ok(Session::with_request(protocol, answer, encode_request(protocol, hostname, port)?))
+    }
+
+    // What `get_status` sends: 0xFE 0x01, accepting either a 1.4+ or a beta answer. Servers
+    // older than 1.4 read the 0xFE and ignore the rest.
+    pub fn any_legacy() -> Session {
+        Session::with_request(PingProtocol::Legacy14, Answer::AnyKick, vec![0xFE, 0x01])
+    }
+
+    // Follow a modern status with a Ping and time the Pong. Ignored for legacy protocols.
+    pub fn measure_latency(mut self, measure: bool) -> Session {
+        self.measure_latency = measure;
+        self
+    }
+
+    fn with_request(protocol: PingProtocol, answer: Answer, request: Vec<u8>) -> Session {
+        Session {
+            protocol,
+            answer,
+            measure_latency: false,
+            outgoing: request,
+            incoming: Vec::new(),
+            state: State::AwaitingStatus
+        }
+    }
+
+    // Bytes to send to the server, if any are pending. `now` is taken as the moment they leave.
+    pub fn poll_transmit(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if self.outgoing.is_empty() {
+            return None;
+        }
+        if let State::AwaitingPong { sent, .. } = &mut self.state {
+            sent.get_or_insert(now);
+        }
+        Some(std::mem::take(&mut self.outgoing))
+    }
+
+    // Feeds bytes received from the server, returning the outcome once the exchange is complete.
+    pub fn handle_input(&mut self, data: &[u8], now: Instant) -> Result<Option<Outcome>, PingError> {
+        self.incoming.extend_from_slice(data);
+
+        match std::mem::replace(&mut self.state, State::Finished) {
+            State::AwaitingStatus => {
+                let status = match self.answer {
+                    // Servers older than 1.7 take the handshake for a bad packet and kick.
+                    Answer::Json if self.incoming.starts_with(&[0xFF, 0x00]) => {
+                        return Err(PingError::UnsupportedProtocol(self.protocol));
+                    }
+                    Answer::Json => match take_packet(&mut self.incoming)? {
+                        Some(packet) => parse_status_packet(&packet)?,
+                        None => return self.wait(State::AwaitingStatus)
+                    },
+                    kick => match take_kick(&mut self.incoming, self.protocol)? {
+                        Some(response) => match kick {
+                            // Servers older than 1.4 ignore the 0x01 and answer in the beta format.
+                            Answer::Legacy if !response.starts_with("\u{00a7}1") => {
+                                return Err(PingError::UnsupportedProtocol(self.protocol));
+                            }
+                            Answer::Legacy => parse_legacy_response(&response)?,
+                            _ => parse_any_kick(&response)?
+                        },
+                        None => return self.wait(State::AwaitingStatus)
+                    }
+                };
+
+                if self.measure_latency && matches!(self.answer, Answer::Json) {
+                    let payload = crate::unix_millis();
+                    self.outgoing.extend_from_slice(&encode_ping(payload)?);
+                    // The Pong cannot have arrived yet, so there is nothing more to look at.
+                    return self.wait(State::AwaitingPong { status: Box::new(status), payload, sent: None });
+                }
+                Ok(Some(Outcome { status, latency: None }))
+            }
+            State::AwaitingPong { status, payload, sent } => match take_packet(&mut self.incoming)? {
+                Some(packet) => {
+                    parse_pong(&packet, payload)?;
+                    let latency = now.saturating_duration_since(sent.unwrap_or(now));
+                    Ok(Some(Outcome { status: *status, latency: Some(latency) }))
+                }
+                None => self.wait(State::AwaitingPong { status, payload, sent })
+            },
+            State::Finished => Ok(None)
+        }
+    }
+
+    // Tells the session the connection ended or failed before `handle_input` returned an outcome.
+    // A status still waiting for its Pong is returned without a latency, as servers may hang up
+    // after the status instead of answering the Ping.
+    pub fn handle_close(&mut self) -> Option<Outcome> {
+        match std::mem::replace(&mut self.state, State::Finished) {
+            State::AwaitingPong { status, .. } => Some(Outcome { status: *status, latency: None }),
+            _ => None
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Finished)
+    }
+
+    fn wait(&mut self, state: State) -> Result<Option<Outcome>, PingError> {
+        self.state = state;
+        Ok(None)
+    }
+}
+
+// Removes one VarInt-framed packet from the front of `buffer`, if it has fully arrived.
+fn take_packet(buffer: &mut Vec<u8>) -> Result<Option<Vec<u8>>, PingError> {
+    let mut reader = Cursor::new(&buffer[..]);
+    let len = match reader.read_var_i32() {
+        Ok(len) => len,
+        Err(e) if e.kind() == IoErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into())
+    };
+    if !(0..=MAX_PACKET_LENGTH).contains(&len) {
+        return Err(IoError::new(IoErrorKind::InvalidData, "Packet length out of range").into());
+    }
+
+    let start = reader.position() as usize;
+    let end = start + len as usize;
+    if buffer.len() < end {
+        return Ok(None);
+    }
+    let packet = buffer[start..end].to_vec();
+    buffer.drain(..end);
+    Ok(Some(packet))
+}
+
+// Removes the 0xFF kick packet legacy servers answer with, if it has fully arrived.
+fn take_kick(buffer: &mut Vec<u8>, protocol: PingProtocol) -> Result<Option<String>, PingError> {
+    let packet_id = match buffer.first() {
+        Some(packet_id) => *packet_id,
+        None => return Ok(None)
+    };
+    if packet_id != 0xFF {
+        return Err(PingError::UnsupportedProtocol(protocol));
+    }
+    if buffer.len() < 3 {
+        return Ok(None);
+    }
+
+    let end = 3 + 2 * u16::from_be_bytes([buffer[1], buffer[2]]) as usize;
+    if buffer.len() < end {
+        return Ok(None);
+    }
+    let response = Cursor::new(&buffer[1..end]).read_utf16_string()?;
+    buffer.drain(..end);
+    Ok(Some(response))
+}
+
+// Everything a client sends for the given era before waiting for the answer.
+fn encode_request(protocol: PingProtocol, hostname: &str, port: u16) -> Result<Vec<u8>, PingError> {
+    let mut request = Vec::new();
+    match protocol {
+        PingProtocol::Beta => request.write_u8(0xFE)?,
+        PingProtocol::Legacy14 => request.write_all(&[0xFE, 0x01])?,
+        PingProtocol::Legacy16 => {
+            request.write_all(&[0xFE, 0x01, 0xFA])?;
+            request.write_utf16_string("MC|PingHost")?;
+            // protocol version byte + hostname string + port
+            let length = u16::try_from(7 + 2 * hostname.encode_utf16().count())
+                .map_err(|_| IoError::new(IoErrorKind::InvalidInput, "Hostname too long"))?;
+            request.write_u16::<BE>(length)?;
+            request.write_u8(LEGACY_PROTOCOL_VERSION)?;
+            request.write_utf16_string(hostname)?;
+            request.write_i32::<BE>(port as i32)?;
+        }
+        PingProtocol::Modern => {
+            let mut handshake = Vec::new();
+            handshake.write_var_i32(0x00)?;
+            handshake.write_var_i32(PROTOCOL_VERSION)?;
+            handshake.write_utf8_string(hostname)?;
+            handshake.write_u16::<BE>(port)?;
+            handshake.write_var_i32(1)?; // next state: status
+            request.write_packet(&handshake)?;
+
+            let mut status_request = Vec::new();
+            status_request.write_var_i32(0x00)?;
+            request.write_packet(&status_request)?;
+        }
+    }
+    Ok(request)
+}
+
+fn encode_ping(payload: i64) -> Result<Vec<u8>, PingError> {
+    let mut ping = Vec::new();
+    ping.write_var_i32(0x01)?;
+    ping.write_i64::<BE>(payload)?;
+
+    let mut frame = Vec::new();
+    frame.write_packet(&ping)?;
+    Ok(frame)
+}
+
+fn parse_any_kick(response: &str) -> Result<Status, PingError> {
+    if response.starts_with("\u{00a7}1") {
+        parse_legacy_response(response)
+    } else {
+        parse_beta_response(response)
+    }
+}
+
+fn parse_status_packet(packet: &[u8]) -> Result<Status, PingError> {
+    let mut response = Cursor::new(packet);
+    match response.read_var_i32()? {
+        0x00 => parse_status_json(&response.read_utf8_string()?),
+        // Encryption request, login success or set compression: the handshake went to login.
+        0x01..=0x03 => Err(PingError::LoginOnly(None)),
+        packet_id => Err(PingError::UnexpectedPacketId(packet_id))
+    }
+}
+
+fn parse_pong(packet: &[u8], payload: i64) -> Result<(), PingError> {
+    let mut pong = Cursor::new(packet);
+    let packet_id = pong.read_var_i32()?;
+    if packet_id != 0x01 {
+        return Err(PingError::UnexpectedPacketId(packet_id));
+    }
+    let echoed = pong.read_i64::<BE>()?;
+    if echoed != payload {
+        return Err(PingError::PongMismatch(echoed));
+    }
+    Ok(())
+}
+
+fn parse_legacy_response(response: &str) -> Result<Status, PingError> {
+    let fields: Vec<&str> = response.split('\u{0}').collect();
+    let field = |index: usize, name: &'static str| {
+        fields.get(index).copied().ok_or_else(|| PingError::malformed(name, response))
+    };
+
+    if field(0, "header")? != "\u{00a7}1" {
+        return Err(PingError::malformed("header", response));
+    }
+
+    Ok(Status {
+        dirty: true,
+        version: Some(Version {
+            protocol: crate::parse_field(field(1, "protocol")?, "protocol", response)?,
+            server: String::from(field(2, "version")?)
+        }),
+        motd: ChatComponent::from_legacy(field(3, "motd")?),
+        favicon: None,
+        players: Players::new(
+            crate::parse_field(field(4, "online")?, "online", response)?,
+            crate::parse_field(field(5, "max")?, "max", response)?
+        ),
+        modded: None
+    })
+}
+
+fn parse_beta_response(response: &str) -> Result<Status, PingError> {
+    // The MOTD comes first and may itself contain the separator, so split from the right.
+    let mut fields = response.rsplitn(3, '\u{00a7}');
+    let max = fields.next().ok_or_else(|| PingError::malformed("max", response))?;
+    let online = fields.next().ok_or_else(|| PingError::malformed("online", response))?;
+    let motd = fields.next().ok_or_else(|| PingError::malformed("motd", response))?;
+
+    Ok(Status {
+        dirty: true,
+        version: None,
+        motd: ChatComponent::from_legacy(motd),
+        favicon: None,
+        players: Players::new(
+            crate::parse_field(online, "online", response)?,
+            crate::parse_field(max, "max", response)?
+        ),
+        modded: None
+    })
+}
+
+fn parse_status_json(json: &str) -> Result<Status, PingError> {
+    let value: Value = serde_json::from_str(json)?;
+
+    // A login disconnect carries a bare chat component where the status object should be.
+    let chat_only = value.get("players").is_none() && (value.get("text").is_some() || value.get("translate").is_some());
+    if value.is_string() || chat_only {
+        return Err(PingError::LoginOnly(Some(ChatComponent::from_json(&value))));
+    }
+
+    let version = match value.get("version") {
+        Some(version) => Some(Version {
+            protocol: version.get("protocol")
+                .and_then(Value::as_i64)
+                .and_then(|protocol| i32::try_from(protocol).ok())
+                .ok_or_else(|| PingError::malformed("version.protocol", json))?,
+            server: version.get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| PingError::malformed("version.name", json))?
+                .to_owned()
+        }),
+        None => None
+    };
+
+    // Vanilla leaves out `players` when the server hides its player count.
+    let players = match value.get("players") {
+        Some(players) => Players::from_json(players).ok_or_else(|| PingError::malformed("players", json))?,
+        None => Players::default()
+    };
+
+    Ok(Status {
+        dirty: false,
+        version,
+        motd: value.get("description").map(ChatComponent::from_json).unwrap_or_default(),
+        // A broken icon should not cost us the rest of the status.
+        favicon: value.get("favicon")
+            .and_then(Value::as_str)
+            .and_then(|uri| Favicon::from_data_uri(uri).ok()),
+        players,
+        modded: ModdedInfo::from_json(&value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kick(response: &str) -> Vec<u8> {
+        let mut kick = vec![0xFF];
+        kick.write_utf16_string(response).unwrap();
+        kick
+    }
+
+    fn frame(packet_id: i32, body: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.write_var_i32(packet_id).unwrap();
+        packet.extend_from_slice(body);
+        let mut frame = Vec::new();
+        frame.write_packet(&packet).unwrap();
+        frame
+    }
+
+    fn status_frame(json: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.write_utf8_string(json).unwrap();
+        frame(0x00, &body)
+    }
+
+    const STATUS_JSON: &str = r#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":3},"description":"A server"}"#;
+    const LEGACY_RESPONSE: &str = "\u{00a7}1\u{0}78\u{0}1.6.4\u{0}A server\u{0}3\u{0}20";
+
+    fn feed(session: &mut Session, data: &[u8]) -> Result<Option<Outcome>, PingError> {
+        session.handle_input(data, Instant::now())
+    }
+
+    #[test]
+    fn modern_request() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap();
+        let request = session.poll_transmit(Instant::now()).unwrap();
+        // Handshake: length, id 0x00, protocol 47, "localhost", 25565, next state 1.
+        assert_eq!(&request[..4], &[0x0F, 0x00, 47, 9]);
+        assert_eq!(&request[4..13], b"localhost");
+        assert_eq!(&request[13..], &[0x63, 0xDD, 0x01, 0x01, 0x00]);
+        assert_eq!(session.poll_transmit(Instant::now()), None);
+    }
+
+    #[test]
+    fn modern_status_split_across_reads() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap();
+        let response = status_frame(STATUS_JSON);
+        for byte in &response[..response.len() - 1] {
+            assert_eq!(feed(&mut session, &[*byte]).unwrap(), None);
+        }
+        let outcome = feed(&mut session, &response[response.len() - 1..]).unwrap().unwrap();
+        assert_eq!(outcome.status.version, Some(Version { protocol: 765, server: String::from("1.20.4") }));
+        assert_eq!(outcome.status.players, Players::new(3, 20));
+        assert_eq!(outcome.latency, None);
+        assert!(session.is_finished());
+    }
+
+    #[test]
+    fn legacy_kick_split_across_reads() {
+        let mut session = Session::new(PingProtocol::Legacy16, "localhost", 25565).unwrap();
+        let response = kick(LEGACY_RESPONSE);
+        // Cut inside the length prefix, then inside a UTF-16 code unit.
+        assert_eq!(feed(&mut session, &response[..2]).unwrap(), None);
+        assert_eq!(feed(&mut session, &response[2..8]).unwrap(), None);
+        let outcome = feed(&mut session, &response[8..]).unwrap().unwrap();
+        assert_eq!(outcome.status.version, Some(Version { protocol: 78, server: String::from("1.6.4") }));
+        assert_eq!(outcome.status.motd.to_plain(), "A server");
+        assert_eq!(outcome.status.players, Players::new(3, 20));
+    }
+
+    #[test]
+    fn beta_request() {
+        let mut session = Session::new(PingProtocol::Beta, "localhost", 25565).unwrap();
+        assert_eq!(session.poll_transmit(Instant::now()), Some(vec![0xFE]));
+        let outcome = feed(&mut session, &kick("A \u{00a7} server\u{00a7}3\u{00a7}20")).unwrap().unwrap();
+        assert_eq!(outcome.status.version, None);
+        assert_eq!(outcome.status.motd.to_plain(), "A \u{00a7} server");
+        assert_eq!(outcome.status.players, Players::new(3, 20));
+    }
+
+    #[test]
+    fn beta_request_with_legacy_answer() {
+        let mut session = Session::new(PingProtocol::Beta, "localhost", 25565).unwrap();
+        let outcome = feed(&mut session, &kick(LEGACY_RESPONSE)).unwrap().unwrap();
+        assert_eq!(outcome.status.version, Some(Version { protocol: 78, server: String::from("1.6.4") }));
+        assert_eq!(outcome.status.players, Players::new(3, 20));
+    }
+
+    #[test]
+    fn legacy_request_with_beta_answer() {
+        let mut session = Session::new(PingProtocol::Legacy14, "localhost", 25565).unwrap();
+        let result = feed(&mut session, &kick("A server\u{00a7}3\u{00a7}20"));
+        assert!(matches!(result, Err(PingError::UnsupportedProtocol(PingProtocol::Legacy14))));
+    }
+
+    #[test]
+    fn modern_request_with_legacy_answer() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap();
+        let result = feed(&mut session, &kick("Outdated client!"));
+        assert!(matches!(result, Err(PingError::UnsupportedProtocol(PingProtocol::Modern))));
+    }
+
+    #[test]
+    fn legacy_request_with_modern_answer() {
+        let mut session = Session::new(PingProtocol::Legacy16, "localhost", 25565).unwrap();
+        let result = feed(&mut session, &status_frame(STATUS_JSON));
+        assert!(matches!(result, Err(PingError::UnsupportedProtocol(PingProtocol::Legacy16))));
+    }
+
+    #[test]
+    fn short_kick() {
+        let mut session = Session::new(PingProtocol::Legacy14, "localhost", 25565).unwrap();
+        let result = feed(&mut session, &kick("\u{00a7}1\u{0}78\u{0}1.6.4"));
+        assert!(matches!(result, Err(PingError::MalformedResponse { field: "motd", .. })));
+
+        let mut session = Session::new(PingProtocol::Beta, "localhost", 25565).unwrap();
+        let result = feed(&mut session, &kick("A server\u{00a7}20"));
+        assert!(matches!(result, Err(PingError::MalformedResponse { field: "motd", .. })));
+
+        let mut session = Session::any_legacy();
+        assert!(matches!(feed(&mut session, &kick("")), Err(PingError::MalformedResponse { field: "online", .. })));
+    }
+
+    #[test]
+    fn garbage_kick() {
+        let mut session = Session::new(PingProtocol::Legacy14, "localhost", 25565).unwrap();
+        let result = feed(&mut session, &kick("\u{00a7}1\u{0}abc\u{0}1.6.4\u{0}A server\u{0}3\u{0}20"));
+        match result {
+            Err(PingError::MalformedResponse { field, raw }) => {
+                assert_eq!(field, "protocol");
+                assert!(raw.contains("abc"));
+            }
+            other => panic!("unexpected result: {:?}", other)
+        }
+
+        let mut session = Session::any_legacy();
+        let result = feed(&mut session, &kick("A server\u{00a7}many\u{00a7}20"));
+        assert!(matches!(result, Err(PingError::MalformedResponse { field: "online", .. })));
+    }
+
+    #[test]
+    fn odd_length_kick() {
+        // A UTF-16 string cut short by a byte can never complete.
+        let mut session = Session::any_legacy();
+        assert_eq!(feed(&mut session, &[0xFF, 0x00, 0x01, 0x00]).unwrap(), None);
+    }
+
+    #[test]
+    fn login_only() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap();
+        let result = feed(&mut session, &frame(0x02, &[0; 17]));
+        assert!(matches!(result, Err(PingError::LoginOnly(None))));
+
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap();
+        let result = feed(&mut session, &status_frame(r#"{"text":"Server is whitelisted"}"#));
+        match result {
+            Err(PingError::LoginOnly(Some(reason))) => assert_eq!(reason.to_plain(), "Server is whitelisted"),
+            other => panic!("unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn players_optional() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap();
+        let outcome = feed(&mut session, &status_frame(r#"{"version":{"name":"1.20.4","protocol":765},"description":"Hidden"}"#)).unwrap().unwrap();
+        assert_eq!(outcome.status.players, Players::default());
+        assert_eq!(outcome.status.motd.to_plain(), "Hidden");
+
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap();
+        let result = feed(&mut session, &status_frame(r#"{"players":{"max":"many","online":0}}"#));
+        assert!(matches!(result, Err(PingError::MalformedResponse { field: "players", .. })));
+    }
+
+    #[test]
+    fn unexpected_packet() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap();
+        assert!(matches!(feed(&mut session, &frame(0x2A, &[])), Err(PingError::UnexpectedPacketId(0x2A))));
+    }
+
+    #[test]
+    fn oversized_frame() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap();
+        let mut frame = Vec::new();
+        frame.write_var_i32(MAX_PACKET_LENGTH + 1).unwrap();
+        assert!(matches!(feed(&mut session, &frame), Err(PingError::Io(e)) if e.kind() == IoErrorKind::InvalidData));
+    }
+
+    fn ping_payload(session: &mut Session, now: Instant) -> i64 {
+        let ping = session.poll_transmit(now).unwrap();
+        assert_eq!(&ping[..2], &[0x09, 0x01]);
+        i64::from_be_bytes(ping[2..].try_into().unwrap())
+    }
+
+    #[test]
+    fn latency() {
+        let start = Instant::now();
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap().measure_latency(true);
+        session.poll_transmit(start);
+        assert_eq!(session.handle_input(&status_frame(STATUS_JSON), start).unwrap(), None);
+
+        let payload = ping_payload(&mut session, start);
+        let pong = frame(0x01, &payload.to_be_bytes());
+        let outcome = session.handle_input(&pong, start + Duration::from_millis(25)).unwrap().unwrap();
+        assert_eq!(outcome.latency, Some(Duration::from_millis(25)));
+        assert_eq!(outcome.status.players, Players::new(3, 20));
+    }
+
+    #[test]
+    fn pong_mismatch() {
+        let start = Instant::now();
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap().measure_latency(true);
+        session.poll_transmit(start);
+        session.handle_input(&status_frame(STATUS_JSON), start).unwrap();
+
+        let payload = ping_payload(&mut session, start);
+        let pong = frame(0x01, &(payload + 1).to_be_bytes());
+        let result = session.handle_input(&pong, start);
+        assert!(matches!(result, Err(PingError::PongMismatch(echoed)) if echoed == payload + 1));
+    }
+
+    #[test]
+    fn closed_before_pong() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap().measure_latency(true);
+        session.poll_transmit(Instant::now());
+        assert_eq!(feed(&mut session, &status_frame(STATUS_JSON)).unwrap(), None);
+        let outcome = session.handle_close().unwrap();
+        assert_eq!(outcome.status.players, Players::new(3, 20));
+        assert_eq!(outcome.latency, None);
+        assert!(session.is_finished());
+    }
+
+    #[test]
+    fn closed_before_status() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap().measure_latency(true);
+        session.poll_transmit(Instant::now());
+        let response = status_frame(STATUS_JSON);
+        assert_eq!(feed(&mut session, &response[..10]).unwrap(), None);
+        assert_eq!(session.handle_close(), None);
+    }
+
+    #[test]
+    fn closed_after_pong_mismatch() {
+        let mut session = Session::new(PingProtocol::Modern, "localhost", 25565).unwrap().measure_latency(true);
+        session.poll_transmit(Instant::now());
+        feed(&mut session, &status_frame(STATUS_JSON)).unwrap();
+        let payload = ping_payload(&mut session, Instant::now());
+        assert!(feed(&mut session, &frame(0x01, &(payload ^ 1).to_be_bytes())).is_err());
+        assert_eq!(session.handle_close(), None);
+    }
+
+    #[test]
+    fn latency_ignored_for_legacy() {
+        let mut session = Session::new(PingProtocol::Legacy16, "localhost", 25565).unwrap().measure_latency(true);
+        session.poll_transmit(Instant::now());
+        let outcome = feed(&mut session, &kick(LEGACY_RESPONSE)).unwrap().unwrap();
+        assert_eq!(outcome.latency, None);
+        assert_eq!(session.poll_transmit(Instant::now()), None);
+    }
+}